@@ -1,4 +1,4 @@
-/**
+/*
  * Copyright 2019 4206. All rights reserved.
  *
  * Permission is hereby granted, free of charge, to any person obtaining a copy of this software
@@ -26,10 +26,13 @@ extern crate getopts;
 use dotext::*;
 use dotext::docx_comments::*;
 use dotext::docx_highlights::*;
-use dotext::docx_highlights::RangeId as HightlightRangeId;
+use dotext::docx_ranges::*;
 
 use getopts::Options;
 use std::env;
+use std::io;
+use std::io::Read;
+use std::process;
 
 
 /// Read the comments in a docx file
@@ -41,12 +44,24 @@ fn main(){
     let mut opts = Options::new();
     //opts.optopt("o", "", "set output file name", "NAME");
     opts.optflag("c", "comments", "extract comments");
+    opts.optflag("m", "metadata", "print author and date of comments");
+    opts.optflag("", "markdown", "print comments as Markdown (with -c)");
     opts.optflag("d", "commented", "extract ranges referenced by comments");
+    opts.optflag("l", "lenient", "close ranges that are never closed at the end of their paragraph (with -d)");
+    opts.optflag("o", "offsets", "print paragraphs and character offsets of commented or highlighted ranges (with -d or -h)");
+    opts.optopt("", "context", "print N characters of context around commented ranges (with -d)", "N");
+    opts.optopt("", "sentences", "print N sentences of context around commented ranges (with -d)", "N");
+    opts.optflag("x", "text", "extract the plain text of the document");
+    opts.optflag("j", "joined", "extract comments joined with the ranges they refer to");
+    opts.optflag("t", "threads", "extract comment threads, replies are indented");
+    opts.optflag("u", "unresolved", "skip resolved comment threads (with -t)");
     opts.optflag("h", "highlighted", "extract highlighted ranges");
+    opts.optflag("s", "shading", "count shaded text as highlighted, with the nearest highlight color (with -h)");
+    opts.optflag("a", "annotated", "extract commented and highlighted ranges with their kind and position");
     opts.optflag("", "help", "print this help menu");
     let matches = match opts.parse(&args[1..]) {
         Ok(m) => { m }
-        Err(f) => { panic!(f.to_string()) }
+        Err(f) => { panic!("{}", f) }
     };
     if matches.opt_present("help") {
         print_usage(&program_name, opts);
@@ -60,57 +75,169 @@ fn main(){
         return;
     };
 
+    let mut docx = if input_path == "-" {
+        let mut bytes = Vec::new();
+        io::stdin().read_to_end(&mut bytes).unwrap_or_else(|e| {
+            eprintln!("{}: {}", input_path, e);
+            process::exit(1)
+        });
+        or_exit(Docx::from_vec(bytes), &input_path)
+    } else {
+        or_exit(Docx::open(&input_path), &input_path)
+    };
     let mut matched = false;
 
     if matches.opt_present("c") {
         matched = true;
-        let comments = Docx::open_comments(&input_path).expect("Cannot open file");
-        for (i,comment_i) in comments.iter().enumerate()
+        let comments = or_exit(docx.comments(), &input_path);
+        for comment_i in comments.iter()
         {
             // TODO: escape doublequotes and newlines
             let comment_id_i = comment_i.id();
-            let cstring_comment_i = escape_as_cstr(comment_i.text());
-            println!("{} \"{}\"", comment_id_i, cstring_comment_i);
+            let cstring_comment_i = if matches.opt_present("markdown") {
+                escape_as_cstr(&comment_i.markdown())
+            } else {
+                escape_as_cstr(comment_i.text())
+            };
+            if matches.opt_present("m") {
+                let author_i = escape_as_cstr(comment_i.author().unwrap_or(""));
+                let date_i = comment_i.date().map(|d| d.to_rfc3339()).unwrap_or_default();
+                println!("{} \"{}\" {} \"{}\"", comment_id_i, author_i, date_i, cstring_comment_i);
+            } else {
+                println!("{} \"{}\"", comment_id_i, cstring_comment_i);
+            }
         }
     }
 
-    if matches.opt_present("d") {
+    let window = match (matches.opt_str("context"), matches.opt_str("sentences")) {
+        (Some(n), _) => Some(ContextWindow::Chars(n.parse().expect("--context expects a number"))),
+        (None, Some(n)) => Some(ContextWindow::Sentences(n.parse().expect("--sentences expects a number"))),
+        (None, None) => None
+    };
+
+    if let (true, Some(window)) = (matches.opt_present("d"), window) {
+        matched = true;
+        let commented = or_exit(docx.commented_in_context(window), &input_path);
+        for (comment_i, context_i) in commented.iter()
+        {
+            println!("{} \"{}\" \"{}\" \"{}\"", comment_i.id(),
+                escape_as_cstr(context_i.before()), escape_as_cstr(comment_i.text()), escape_as_cstr(context_i.after()));
+        }
+    } else if matches.opt_present("d") {
         matched = true;
-        let commented = Docx::open_commented(&input_path).expect("Cannot open file");
-        for (i,comment_i) in commented.iter().enumerate()
+        let recovery = if matches.opt_present("l") { RangeRecovery::CloseAtParagraphEnd } else { RangeRecovery::Drop };
+        let (commented, diagnostics) = or_exit(docx.commented_with_diagnostics(recovery), &input_path);
+        for diagnostic_i in diagnostics.iter() {
+            eprintln!("Malformed document: {}", diagnostic_i);
+        }
+        for comment_i in commented.iter()
         {
             // TODO: escape doublequotes and newlines
             let commented_id_i = comment_i.id();
             let cstring_comment_i = escape_as_cstr(comment_i.text());
-            println!("{} \"{}\"", commented_id_i, cstring_comment_i);
+            if matches.opt_present("o") {
+                let (start_i, end_i) = (comment_i.start(), comment_i.end());
+                println!("{} {} {}:{}-{}:{} \"{}\"", commented_id_i, comment_i.part(),
+                    start_i.paragraph, start_i.offset, end_i.paragraph, end_i.offset, cstring_comment_i);
+            } else {
+                println!("{} \"{}\"", commented_id_i, cstring_comment_i);
+            }
+        }
+    }
+
+    if matches.opt_present("x") {
+        matched = true;
+        let mut text = String::new();
+        docx.read_to_string(&mut text).unwrap_or_else(|e| {
+            eprintln!("{}: {}", input_path, e);
+            process::exit(1)
+        });
+        print!("{}", text);
+    }
+
+    if matches.opt_present("j") {
+        matched = true;
+        let records = or_exit(docx.comment_records(), &input_path);
+        for record_i in records.iter()
+        {
+            // a missing counterpart is printed as unquoted "-"
+            let comment_i = match record_i.comment() {
+                Some(c) => format!("\"{}\"", escape_as_cstr(c.text())),
+                None => "-".to_string()
+            };
+            let commented_i = match record_i.commented() {
+                Some(c) => format!("\"{}\"", escape_as_cstr(c.text())),
+                None => "-".to_string()
+            };
+            println!("{} {} {}", record_i.id(), comment_i, commented_i);
+        }
+    }
+
+    if matches.opt_present("t") {
+        matched = true;
+        let threads = or_exit(docx.comment_threads(), &input_path);
+        for thread_i in threads.iter().filter(|t| !(matches.opt_present("u") && t.is_resolved()))
+        {
+            let root_i = thread_i.root();
+            let resolved_i = if thread_i.is_resolved() { " (resolved)" } else { "" };
+            println!("{} \"{}\"{}", root_i.id(), escape_as_cstr(root_i.text()), resolved_i);
+            for reply_i in thread_i.replies() {
+                println!("  {} \"{}\"", reply_i.id(), escape_as_cstr(reply_i.text()));
+            }
         }
     }
 
     if matches.opt_present("h") {
         matched = true;
-        let (stringtable,highlighted) = Docx::open_highlighted(&input_path).expect("Cannot open file");
+        let shading = if matches.opt_present("s") { Shading::AsHighlight } else { Shading::Ignore };
+        let highlighted = or_exit(docx.highlighted_with(shading), &input_path);
         for highlighted_i in highlighted.iter()
         {
-            let h_id = highlighted_i.id();
-            let h_value = match stringtable.get(&h_id) { Some(color) => color, None => "??" };
             let cstring_highlighted_i = escape_as_cstr(highlighted_i.text());
-            println!("{} \"{}\"", h_value, cstring_highlighted_i);
+            if matches.opt_present("o") {
+                // a range that continues the previous one after a paragraph break is marked with "+"
+                let (start_i, end_i) = (highlighted_i.start(), highlighted_i.end());
+                let continues_i = if highlighted_i.continues() { "+" } else { "" };
+                println!("{} {}{}:{}-{}:{} \"{}\"", highlighted_i.label(), continues_i,
+                    start_i.paragraph, start_i.offset, end_i.paragraph, end_i.offset, cstring_highlighted_i);
+            } else {
+                println!("{} \"{}\"", highlighted_i.label(), cstring_highlighted_i);
+            }
+        }
+    }
+
+    if matches.opt_present("a") {
+        matched = true;
+        let ranges = or_exit(docx.annotated_ranges(), &input_path);
+        for range_i in ranges.iter()
+        {
+            let (start_i, end_i) = (range_i.start(), range_i.end());
+            println!("{} {} {} {}:{}-{}:{} \"{}\"", range_i.kind(), range_i.label(), range_i.part(),
+                start_i.paragraph, start_i.offset, end_i.paragraph, end_i.offset, escape_as_cstr(range_i.text()));
         }
     }
 
     if ! matched {
         print_usage(&program_name, opts);
-        return;
     }
 }
 
 
 fn print_usage(program_name: &str, opts: Options) {
-    let brief = format!("Usage: {} OPTIONS FILE", program_name);
+    let brief = format!("Usage: {} OPTIONS FILE (- reads from stdin)", program_name);
     print!("{}", opts.usage(&brief));
 }
 
 
+/// print the error, e.g. for a file that is not a docx, and exit
+fn or_exit<T>(res: Result<T, Error>, input_path: &str) -> T {
+    res.unwrap_or_else(|e| {
+        eprintln!("{}: {}", input_path, e);
+        process::exit(1)
+    })
+}
+
+
 // TODO: use a real escaping function instead of this dummy
 fn escape_as_cstr(s: &str) -> String {
     s.replace("\"","\\\"").replace("\n","\\n").to_string()
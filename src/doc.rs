@@ -13,6 +13,8 @@ use std::io;
 use std::clone::Clone;
 use zip::read::ZipFile;
 
+use error::{Error, Result};
+
 pub trait HasKind {
 
     // kind
@@ -23,24 +25,24 @@ pub trait HasKind {
 }
 
 pub trait MsDoc<T>: Read + HasKind {
-    fn open<P: AsRef<Path>>(path: P) -> io::Result<T>;
+    fn open<P: AsRef<Path>>(path: P) -> Result<T>;
 }
 
 pub trait OpenOfficeDoc<T>: Read + HasKind {
-    fn open<P: AsRef<Path>>(path: P) -> io::Result<T>;
+    fn open<P: AsRef<Path>>(path: P) -> Result<T>;
 }
 
 
-pub(crate) fn open_doc_read_data<P: AsRef<Path>>(path: P, content_name:&str, tags:&[&str]) -> io::Result<String> {
+pub(crate) fn open_doc_read_data<P: AsRef<Path>>(path: P, content_name:&str, tags:&[&str]) -> Result<String> {
     let file = File::open(path.as_ref())?;
     let mut archive = ZipArchive::new(file)?;
 
     let mut xml_data = String::new();
 
     for i in 0..archive.len(){
-        let mut c_file = archive.by_index(i).unwrap();
+        let mut c_file = archive.by_index(i)?;
         if c_file.name() == content_name {
-            c_file.read_to_string(&mut xml_data);
+            c_file.read_to_string(&mut xml_data)?;
             break
         }
     }
@@ -50,7 +52,7 @@ pub(crate) fn open_doc_read_data<P: AsRef<Path>>(path: P, content_name:&str, tag
     let mut buf = Vec::new();
     let mut txt = Vec::new();
 
-    if xml_data.len() > 0 {
+    if !xml_data.is_empty() {
         let mut to_read = false;
         loop {
             match xml_reader.read_event(&mut buf){
@@ -65,18 +67,17 @@ pub(crate) fn open_doc_read_data<P: AsRef<Path>>(path: P, content_name:&str, tag
                         }
                     }
                 },
-                Ok(Event::Text(e)) => {
-                    if to_read {
-                        txt.push(e.unescape_and_decode(&xml_reader).unwrap());
-                        to_read = false;
-                    }
+                Ok(Event::Text(e)) if to_read => {
+                    txt.push(e.unescape_and_decode(&xml_reader)
+                        .map_err(|e| Error::xml(xml_reader.buffer_position(), e).in_part(content_name))?);
+                    to_read = false;
                 },
                 Ok(Event::Eof) => break,
-                Err(e) => panic!("Error at position {}: {:?}", xml_reader.buffer_position(), e),
+                Err(e) => return Err(Error::xml(xml_reader.buffer_position(), e).in_part(content_name)),
                 _ => (),
             }
         }
     }
 
     Ok(txt.join(""))
-}
\ No newline at end of file
+}
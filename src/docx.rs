@@ -1,22 +1,102 @@
-
 use zip::ZipArchive;
 
-use xml::reader::Reader;
-use xml::events::Event;
-
-use std::path::{Path, PathBuf};
+use std::path::Path;
 use std::fs::File;
 use std::io::prelude::*;
 use std::io::Cursor;
 use std::io;
 use std::clone::Clone;
 use zip::read::ZipFile;
+use zip::result::ZipError;
+
+use std::collections::HashMap;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::fmt;
 
 use doc::{MsDoc, HasKind};
+use docx_rels::{REL_HEADER, REL_FOOTER, REL_FOOTNOTES, REL_ENDNOTES};
+use docx_package::find_main_part;
+use docx_events::Element;
+use docx_walk::{Walk, Visitor, walk_part};
+use xml_ns::Ns;
+use error::{Error, Result};
 
+/// a Word document, the zip archive is opened once
+/// parts are read when first needed and kept for further use
 pub struct Docx {
-    path: PathBuf,
-    data: Cursor<String>
+    archive: RefCell<ZipArchive<Box<dyn ReadSeek>>>,
+    main: String, // name of the main document part
+    // the xml of the parts read so far, None for parts missing from the package
+    parts: RefCell<HashMap<String,Option<Rc<String>>>>,
+    data: Option<Cursor<String>> // plain text, read on first use
+}
+
+/// a source of a zip archive, e.g. a file or a `Cursor<Vec<u8>>`
+pub trait ReadSeek: Read + Seek {}
+
+impl<R: Read + Seek> ReadSeek for R {}
+
+/// a position in the plain text of a document
+/// `paragraph` counts the paragraphs of word/document.xml from 0,
+/// `offset` counts characters (not bytes) from the beginning of the text
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TextPosition {
+    pub paragraph: usize,
+    pub offset: usize
+}
+
+/// a part of the package that holds text of the document ("story")
+/// positions are relative to the plain text of their part
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum StoryPart {
+    /// word/document.xml, including tables
+    Main,
+    /// a header, e.g. word/header1.xml
+    Header { name: String, kind: Option<HeaderFooterKind> },
+    /// a footer, e.g. word/footer1.xml
+    Footer { name: String, kind: Option<HeaderFooterKind> },
+    Footnotes,
+    Endnotes
+}
+
+/// the pages a header or footer applies to (`w:type` of its reference in `w:sectPr`)
+/// headers that no section refers to have no kind
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum HeaderFooterKind {
+    Default,
+    First,
+    Even
+}
+
+impl HeaderFooterKind {
+    fn read(kind: &str) -> HeaderFooterKind {
+        match kind {
+            "first" => HeaderFooterKind::First
+           ,"even" => HeaderFooterKind::Even
+           ,_ => HeaderFooterKind::Default
+        }
+    }
+}
+
+impl fmt::Display for StoryPart {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn kind_str(kind: &Option<HeaderFooterKind>) -> &'static str {
+            match *kind {
+                Some(HeaderFooterKind::Default) => "default",
+                Some(HeaderFooterKind::First) => "first",
+                Some(HeaderFooterKind::Even) => "even",
+                None => "unused"
+            }
+        }
+        match *self {
+            StoryPart::Main => write!(f, "document"),
+            StoryPart::Header { ref name, ref kind } => write!(f, "header:{}:{}", kind_str(kind), name),
+            StoryPart::Footer { ref name, ref kind } => write!(f, "footer:{}:{}", kind_str(kind), name),
+            StoryPart::Footnotes => write!(f, "footnotes"),
+            StoryPart::Endnotes => write!(f, "endnotes")
+        }
+    }
 }
 
 impl HasKind for Docx {
@@ -28,3 +108,261 @@ impl HasKind for Docx {
         "docx"
     }
 }
+
+/// opening reads the table of contents of the archive, but no part
+impl MsDoc<Docx> for Docx {
+    fn open<P: AsRef<Path>>(path: P) -> Result<Docx> {
+        Docx::from_reader(File::open(path.as_ref())?)
+    }
+}
+
+/// the plain text of a document is the text of all `w:t`
+/// with a newline at the end of every paragraph
+impl Read for Docx {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.data.is_none() {
+            self.data = Some(Cursor::new(self.plain_text()?));
+        }
+        match self.data {
+            Some(ref mut data) => data.read(buf),
+            None => Ok(0)
+        }
+    }
+}
+
+impl Docx {
+    /// read a document from any seekable source, e.g. an upload held in memory
+    pub fn from_reader<R: Read + Seek + 'static>(reader: R) -> Result<Docx> {
+        let source: Box<dyn ReadSeek> = Box::new(reader);
+        let archive = ZipArchive::new(source)?;
+        let mut docx = Docx {
+            archive: RefCell::new(archive),
+            main: String::new(),
+            parts: RefCell::new(HashMap::new()),
+            data: None
+        };
+        docx.main = find_main_part(&docx)?;
+        if let Err(e) = docx.archive.get_mut().by_name(&docx.main) {
+            return Err(Error::zip_entry(&docx.main, e))
+        }
+        Ok(docx)
+    }
+
+    /// the name of the main document part, usually "word/document.xml"
+    pub fn main_part_name(&self) -> &str {
+        &self.main
+    }
+
+    /// read a document from its bytes
+    pub fn from_vec(bytes: Vec<u8>) -> Result<Docx> {
+        Docx::from_reader(Cursor::new(bytes))
+    }
+
+    /// read a document from its bytes, the bytes are copied
+    pub fn from_bytes(bytes: &[u8]) -> Result<Docx> {
+        Docx::from_vec(bytes.to_vec())
+    }
+
+    /// the xml of a part, e.g. "word/comments.xml"
+    /// post: None if the package does not contain the part
+    pub(crate) fn part(&self, name: &str) -> Result<Option<Rc<String>>> {
+        if let Some(xml_data) = self.parts.borrow().get(name) {
+            return Ok(xml_data.clone());
+        }
+
+        let mut archive = self.archive.borrow_mut();
+        let xml_data = match archive.by_name(name) {
+            Ok(mut c_file) => {
+                let mut xml_data = String::new();
+                c_file.read_to_string(&mut xml_data)?;
+                Some(Rc::new(xml_data))
+            }
+            , Err(ZipError::FileNotFound) => None
+            , Err(e) => return Err(Error::zip_entry(name, e))
+        };
+        self.parts.borrow_mut().insert(name.to_string(), xml_data.clone());
+        Ok(xml_data)
+    }
+
+    /// the zip entry of a part, to stream it instead of reading it at once
+    pub(crate) fn entry<'a>(&'a mut self, name: &str) -> Result<ZipFile<'a>> {
+        self.archive.get_mut().by_name(name).map_err(|e| Error::zip_entry(name, e))
+    }
+
+    /// like `part`, for parts that the document cannot do without
+    pub(crate) fn required_part(&self, name: &str) -> Result<Rc<String>> {
+        self.part(name)?.ok_or_else(|| Error::MissingPart(name.to_string()))
+    }
+
+    fn plain_text(&self) -> Result<String> {
+        let mut extractor = ParagraphExtractor::default();
+        self.visit(&mut [&mut extractor])?;
+        let paragraphs = extractor.finish();
+        let mut text = String::new();
+        for paragraph in paragraphs {
+            text.push_str(&paragraph);
+            text.push('\n');
+        }
+        Ok(text)
+    }
+}
+
+/// the text of each paragraph, without the newline
+#[derive(Default)]
+pub(crate) struct ParagraphExtractor {
+    paragraphs: Vec<String>,
+    txt: String
+}
+
+impl ParagraphExtractor {
+    pub fn finish(self) -> Vec<String> {
+        self.paragraphs
+    }
+}
+
+impl Visitor for ParagraphExtractor {
+    fn paragraph_end(&mut self, _walk: &Walk) {
+        self.paragraphs.push(::std::mem::take(&mut self.txt));
+    }
+
+    fn text(&mut self, text: &str, _walk: &Walk) {
+        self.txt.push_str(text);
+    }
+}
+
+/// a story part with its relationship id (empty for the main document), zip entry name and xml
+type StorySource = (StoryPart,String,String,Rc<String>);
+
+/// the relationship id, zip entry name and xml of every story part: the main document first,
+/// then headers, footers, footnotes and endnotes in order of the relationships of the main document
+/// the kinds of headers and footers are not known yet
+fn read_story_parts(docx: &Docx) -> Result<Vec<StorySource>> {
+    let main = docx.main_part_name();
+    let mut res = vec![(StoryPart::Main, String::new(), main.to_string(), docx.required_part(main)?)];
+    for rel_type in &[REL_HEADER, REL_FOOTER, REL_FOOTNOTES, REL_ENDNOTES] {
+        for (rel, name) in docx.related_parts(rel_type)? {
+            let part = match *rel_type {
+                REL_HEADER => StoryPart::Header { name: name.clone(), kind: None },
+                REL_FOOTER => StoryPart::Footer { name: name.clone(), kind: None },
+                REL_FOOTNOTES => StoryPart::Footnotes,
+                _ => StoryPart::Endnotes
+            };
+            if let Some(xml_data) = docx.part(&name)? {
+                res.push((part, rel.id, name, xml_data));
+            }
+        }
+    }
+
+    Ok(res)
+}
+
+/// walk every story part, the main document first: `f` gets the part
+/// and a function that walks it with the visitors it is given
+/// the kinds of headers and footers are read in the walk of the main document
+pub(crate) fn walk_story_parts<F>(docx: &Docx, mut f: F) -> Result<()>
+    where F: FnMut(&StoryPart, &mut dyn FnMut(&mut [&mut dyn Visitor]) -> Result<Walk>) -> Result<()>
+{
+    let mut kinds = HeaderFooterKinds::default();
+    for (mut part, rel_id, name, xml_data) in read_story_parts(docx)? {
+        match part {
+            StoryPart::Header { ref mut kind, .. } | StoryPart::Footer { ref mut kind, .. } => {
+                *kind = kinds.kinds.get(&rel_id).cloned();
+            }
+            _ => ()
+        }
+        let main = part == StoryPart::Main;
+        let mut walk = |visitors: &mut [&mut dyn Visitor]| {
+            if main {
+                let mut all: Vec<&mut dyn Visitor> = visitors.iter_mut().map(|v| &mut **v as &mut dyn Visitor).collect();
+                all.push(&mut kinds);
+                walk_part(xml_data.as_ref(), &name, &mut all)
+            } else {
+                walk_part(xml_data.as_ref(), &name, visitors)
+            }
+        };
+        f(&part, &mut walk)?;
+    }
+    Ok(())
+}
+
+/// maps the relationship ids of `w:headerReference` and `w:footerReference` to their kind
+/// if sections reuse a header with another kind, the first reference wins
+#[derive(Default)]
+struct HeaderFooterKinds {
+    kinds: HashMap<String,HeaderFooterKind>
+}
+
+impl Visitor for HeaderFooterKinds {
+    fn element_start(&mut self, element: &Element, _walk: &Walk) {
+        if let Some(id) = element.attr(Ns::R, "id") {
+            let kind = HeaderFooterKind::read(element.attr(Ns::W, "type").unwrap_or_default());
+            self.kinds.entry(id.to_string()).or_insert(kind);
+        }
+    }
+
+    fn elements(&self) -> Vec<(Ns, &'static str)> {
+        vec![(Ns::W, "headerReference"), (Ns::W, "footerReference")]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use docx_comments::{ReadComments, Comment};
+    use docx_ranges::RangeId;
+    use std::fs;
+    use zip::write::{ZipWriter, FileOptions};
+    use docx_ranges::AnnotatedRange;
+
+    fn header(name: &str, kind: Option<HeaderFooterKind>) -> StoryPart {
+        StoryPart::Header { name: name.to_string(), kind }
+    }
+
+    #[test]
+    fn opens_documents_in_memory() {
+        let comments = |docx: Docx| -> Vec<(usize, String, Option<String>)> {
+            docx.comments().unwrap().iter().map(|c| (c.id(), c.text().to_string(), c.author().map(String::from))).collect()
+        };
+        let path = "samples/sample-with-comment.docx";
+        let in_memory = comments(Docx::from_vec(fs::read(path).unwrap()).unwrap());
+        assert!(!in_memory.is_empty());
+        assert_eq!(in_memory, comments(Docx::open(path).unwrap()));
+    }
+
+    #[test]
+    fn other_bytes_are_not_a_zip() {
+        match Docx::from_bytes(b"plain text, not a document") {
+            Err(Error::NotAZip(_)) => (),
+            _ => panic!("no NotAZip error for bytes that are not a zip archive")
+        }
+    }
+
+    #[test]
+    fn missing_main_part_is_named() {
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("readme.txt", FileOptions::default()).unwrap();
+        zip.write_all(b"no document").unwrap();
+        let bytes = zip.finish().unwrap().into_inner();
+        match Docx::from_vec(bytes) {
+            Err(Error::MissingPart(ref name)) => assert_eq!(name, "word/document.xml"),
+            _ => panic!("no error for a package without document")
+        }
+    }
+
+    #[test]
+    fn ranges_of_every_story_part() {
+        let docx = Docx::open("samples/sample-with-story-parts.docx").unwrap();
+        let commented = docx.commented().unwrap();
+        let parts: Vec<(&str, &StoryPart)> = commented.iter().map(|c| (c.text(), c.part())).collect();
+        assert_eq!(parts, vec![
+            ("body", &StoryPart::Main),
+            ("default header", &header("word/header1.xml", Some(HeaderFooterKind::Default))),
+            ("first header", &header("word/header2.xml", Some(HeaderFooterKind::First))),
+            ("even footer", &StoryPart::Footer { name: "word/footer1.xml".to_string(), kind: Some(HeaderFooterKind::Even) }),
+            ("footnote", &StoryPart::Footnotes)
+        ]);
+        // positions are those of the plain text of the part
+        let footnote = &commented[4];
+        assert_eq!((footnote.start(), footnote.end()), (TextPosition { paragraph: 1, offset: 1 }, TextPosition { paragraph: 1, offset: 9 }));
+    }
+}
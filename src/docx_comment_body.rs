@@ -0,0 +1,309 @@
+//! the body of a comment: paragraphs made of runs
+//! as written in `<w:comment>` of word/comments.xml
+
+pub struct CommentParagraph {
+    runs: Vec<CommentRun>
+}
+
+pub struct CommentRun {
+    text: String,
+    bold: bool,
+    italic: bool,
+    link: Option<CommentLink>
+}
+
+/// the target of a `w:hyperlink` around a run
+#[derive(Clone, Debug, PartialEq)]
+pub enum CommentLink {
+    /// a hyperlink resolved via word/_rels/comments.xml.rels
+    Url(String),
+    /// a link to a bookmark inside the document (`w:anchor`)
+    Bookmark(String),
+    /// an @mention of a person, with the address it links to (usually "mailto:...")
+    Mention(String)
+}
+
+impl CommentParagraph {
+    /// the runs of text in order of appearance
+    pub fn runs(&self) -> &[CommentRun] {
+        &self.runs
+    }
+
+    /// the runs concatenated
+    pub fn text(&self) -> String {
+        self.runs.iter().map(|r| r.text.as_str()).collect()
+    }
+
+    /// the runs rendered as Markdown
+    /// neighbouring runs with the same formatting are rendered as one
+    pub fn markdown(&self) -> String {
+        let mut res = String::new();
+        let mut i = 0;
+        while i < self.runs.len() {
+            let first = &self.runs[i];
+            let mut text = String::new();
+            while i < self.runs.len() && self.runs[i].same_format(first) {
+                text.push_str(&self.runs[i].text);
+                i += 1;
+            }
+            res.push_str(&markdown_span(&text, first));
+        }
+        res
+    }
+}
+
+impl CommentRun {
+    /// text of the run, tabs and line breaks included as '\t' and '\n'
+    pub fn text(&self) -> &str {
+        self.text.as_str()
+    }
+
+    pub fn is_bold(&self) -> bool {
+        self.bold
+    }
+
+    pub fn is_italic(&self) -> bool {
+        self.italic
+    }
+
+    /// the hyperlink or mention the run belongs to
+    pub fn link(&self) -> Option<&CommentLink> {
+        self.link.as_ref()
+    }
+
+    fn same_format(&self, other: &CommentRun) -> bool {
+        self.bold == other.bold && self.italic == other.italic && self.link == other.link
+    }
+}
+
+/// render paragraphs as plain text, paragraphs are separated by a newline
+pub fn plain_text(paragraphs: &[CommentParagraph]) -> String {
+    paragraphs.iter().map(|p| p.text()).collect::<Vec<_>>().join("\n")
+}
+
+/// render paragraphs as Markdown, paragraphs are separated by an empty line
+pub fn markdown(paragraphs: &[CommentParagraph]) -> String {
+    paragraphs.iter().map(|p| p.markdown()).collect::<Vec<_>>().join("\n\n")
+}
+
+fn markdown_escape(text: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' => { res.push('\\'); res.push(c); }
+            '\n' => res.push_str("  \n"), // hard line break
+            _ => res.push(c)
+        }
+    }
+    res
+}
+
+/// a link target in angle brackets, which may hold spaces and parentheses
+/// e.g. "<https://example.com/a b>"
+fn markdown_destination(target: &str) -> String {
+    let mut res = String::with_capacity(target.len() + 2);
+    res.push('<');
+    for c in target.chars() {
+        match c {
+            '\\' | '<' | '>' => { res.push('\\'); res.push(c); }
+            '\n' => res.push_str("%0A"),
+            '\r' => res.push_str("%0D"),
+            _ => res.push(c)
+        }
+    }
+    res.push('>');
+    res
+}
+
+/// Markdown does not allow emphasis to begin or end with whitespace,
+/// so surrounding whitespace is kept outside of the markers
+fn markdown_span(text: &str, format: &CommentRun) -> String {
+    let inner = text.trim();
+    if inner.is_empty() {
+        return markdown_escape(text);
+    }
+    let leading = &text[..text.len() - text.trim_start().len()];
+    let trailing = &text[text.trim_end().len()..];
+
+    let marker = match (format.bold, format.italic) {
+        (true, true) => "***",
+        (true, false) => "**",
+        (false, true) => "*",
+        (false, false) => ""
+    };
+    let mut span = format!("{}{}{}", marker, markdown_escape(inner), marker);
+    span = match format.link {
+        Some(CommentLink::Url(ref url)) => format!("[{}]({})", span, markdown_destination(url)),
+        Some(CommentLink::Bookmark(ref name)) => format!("[{}]({})", span, markdown_destination(&format!("#{}", name))),
+        Some(CommentLink::Mention(ref address)) => format!("[{}]({})", span, markdown_destination(address)),
+        None => span
+    };
+    format!("{}{}{}", markdown_escape(leading), span, markdown_escape(trailing))
+}
+
+/// collects paragraphs and runs while walking over a `<w:comment>`
+pub(crate) struct CommentBodyBuilder {
+    paragraphs: Vec<CommentParagraph>,
+    runs: Option<Vec<CommentRun>>, // Some while inside a paragraph
+    run: Option<CommentRun>, // Some while inside a run
+    link: Option<CommentLink>, // Some while inside a hyperlink
+    link_start: usize // index of the first run inside the current hyperlink
+}
+
+impl CommentBodyBuilder {
+    pub fn new() -> CommentBodyBuilder {
+        CommentBodyBuilder { paragraphs: vec![], runs: None, run: None, link: None, link_start: 0 }
+    }
+
+    pub fn start_paragraph(&mut self) {
+        self.end_paragraph(); // paragraphs do not nest in comments
+        self.runs = Some(vec![]);
+    }
+
+    pub fn end_paragraph(&mut self) {
+        self.end_run();
+        self.link = None;
+        if let Some(runs) = self.runs.take() {
+            self.paragraphs.push(CommentParagraph { runs });
+        }
+    }
+
+    pub fn start_hyperlink(&mut self, link: Option<CommentLink>) {
+        self.end_run();
+        self.link = link;
+        self.link_start = self.runs.as_ref().map(|r| r.len()).unwrap_or(0);
+    }
+
+    /// Word writes an @mention as a hyperlink to "mailto:" whose text starts with '@'
+    pub fn end_hyperlink(&mut self) {
+        self.end_run();
+        if let (Some(CommentLink::Url(ref target)), Some(ref mut runs)) = (self.link.take(), self.runs.as_mut()) {
+            let link_start = self.link_start.min(runs.len());
+            let linked = &mut runs[link_start..];
+            let is_mention = target.starts_with("mailto:")
+                && linked.first().map(|r| r.text.starts_with('@')).unwrap_or(false);
+            if is_mention {
+                for run in linked.iter_mut() {
+                    run.link = Some(CommentLink::Mention(target.clone()));
+                }
+            }
+        }
+    }
+
+    pub fn start_run(&mut self) {
+        self.end_run();
+        self.run = Some(CommentRun { text: String::new(), bold: false, italic: false, link: self.link.clone() });
+    }
+
+    /// runs without text (e.g. the `w:annotationRef` run) are dropped
+    pub fn end_run(&mut self) {
+        if let Some(run) = self.run.take() {
+            if !run.text.is_empty() {
+                self.runs.get_or_insert_with(Vec::new).push(run);
+            }
+        }
+    }
+
+    /// true while inside a run, where `w:tab` and `w:br` denote text
+    /// and `w:b` and `w:i` denote formatting
+    pub fn in_run(&self) -> bool {
+        self.run.is_some()
+    }
+
+    pub fn set_bold(&mut self, bold: bool) {
+        if let Some(ref mut run) = self.run {
+            run.bold = bold;
+        }
+    }
+
+    pub fn set_italic(&mut self, italic: bool) {
+        if let Some(ref mut run) = self.run {
+            run.italic = italic;
+        }
+    }
+
+    pub fn push_text(&mut self, text: &str) {
+        if self.run.is_none() {
+            self.start_run();
+        }
+        if let Some(ref mut run) = self.run {
+            run.text.push_str(text);
+        }
+    }
+
+    pub fn finish(&mut self) -> Vec<CommentParagraph> {
+        self.end_paragraph();
+        ::std::mem::take(&mut self.paragraphs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn link(text: &str, link: CommentLink) -> CommentRun {
+        CommentRun { text: text.to_string(), bold: false, italic: false, link: Some(link) }
+    }
+
+    fn run(builder: &mut CommentBodyBuilder, text: &str, bold: bool, italic: bool) {
+        builder.start_run();
+        builder.set_bold(bold);
+        builder.set_italic(italic);
+        builder.push_text(text);
+        builder.end_run();
+    }
+
+    fn hyperlink(target: &str, text: &str) -> CommentParagraph {
+        let mut builder = CommentBodyBuilder::new();
+        builder.start_paragraph();
+        builder.start_hyperlink(Some(CommentLink::Url(target.to_string())));
+        run(&mut builder, text, false, false);
+        builder.end_hyperlink();
+        builder.finish().remove(0)
+    }
+
+    #[test]
+    fn emphasis_keeps_whitespace_outside() {
+        let mut builder = CommentBodyBuilder::new();
+        builder.start_paragraph();
+        run(&mut builder, "a", false, false);
+        run(&mut builder, " bold ", true, false);
+        run(&mut builder, "both ", true, true);
+        run(&mut builder, "it", false, true);
+        let paragraphs = builder.finish();
+        assert_eq!(markdown(&paragraphs), "a **bold** ***both*** *it*");
+    }
+
+    #[test]
+    fn markdown_characters_are_escaped() {
+        let mut builder = CommentBodyBuilder::new();
+        builder.start_paragraph();
+        run(&mut builder, "*a_b* [c] #d", false, false);
+        builder.start_paragraph();
+        // the text of w:br
+        run(&mut builder, "line\nbreak", false, false);
+        let paragraphs = builder.finish();
+        assert_eq!(markdown(&paragraphs), "\\*a\\_b\\* \\[c\\] \\#d\n\nline  \nbreak");
+    }
+
+    #[test]
+    fn mentions_are_mailto_links_to_at_names() {
+        let mention = hyperlink("mailto:ann@example.com", "@Ann");
+        assert_eq!(mention.runs()[0].link(), Some(&CommentLink::Mention("mailto:ann@example.com".to_string())));
+        let mail = hyperlink("mailto:ann@example.com", "Ann");
+        assert_eq!(mail.runs()[0].link(), Some(&CommentLink::Url("mailto:ann@example.com".to_string())));
+        let web = hyperlink("https://example.com/", "@home");
+        assert_eq!(web.runs()[0].link(), Some(&CommentLink::Url("https://example.com/".to_string())));
+    }
+
+    #[test]
+    fn link_targets_are_kept_whole() {
+        let paragraph = CommentParagraph { runs: vec![
+            link("wiki", CommentLink::Url("https://en.wikipedia.org/wiki/Rust_(programming language)".to_string())),
+            link(" @Ann", CommentLink::Mention("mailto:ann@example.com".to_string()))
+        ] };
+        assert_eq!(paragraph.markdown(),
+            "[wiki](<https://en.wikipedia.org/wiki/Rust_(programming language)>) [@Ann](<mailto:ann@example.com>)");
+        assert_eq!(markdown_destination("a<b>\\c"), "<a\\<b\\>\\\\c>");
+    }
+}
@@ -5,7 +5,6 @@ use zip::ZipArchive;
 
 use xml::reader::Reader;
 use xml::events::{Event,BytesStart};
-use std::io::{Error, ErrorKind};
 
 use std::path::{Path, PathBuf};
 use std::fs::File;
@@ -14,28 +13,210 @@ use std::io;
 use std::clone::Clone;
 use zip::read::ZipFile;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use chrono::{DateTime, FixedOffset, NaiveDateTime, Utc};
 
 use ::Docx;
+use doc::MsDoc;
+use docx::{TextPosition, StoryPart, ParagraphExtractor, walk_story_parts};
+use docx_walk::{Walk, Visitor, RangeMarker};
+use docx_comment_body::{CommentParagraph, CommentLink, CommentBodyBuilder, plain_text, markdown};
+use docx_rels::{REL_COMMENTS, REL_COMMENTS_EXTENDED};
+use docx_ranges::{RangeId, AnnotatedRange, RangeKind};
 use get_attr::GetAttr;
+use xml_ns::{Ns, NsReader};
+use error::{Error, Result};
 
 pub struct DocxComment {
     id: usize,
-    data: String
+    author: Option<String>,
+    initials: Option<String>,
+    date: Option<DateTime<Utc>>,
+    paragraphs: Vec<CommentParagraph>,
+    data: String, // plain text rendering of the paragraphs
+    para_id: Option<String> // w14:paraId of the last paragraph, links to commentsExtended.xml
+}
+
+/// the text range in the document that a comment refers to
+/// for a point comment (`w:commentReference` without a range), the range is
+/// the insertion point and the text is that of the enclosing paragraph
+pub struct DocxCommented {
+    id: usize,
+    data: String,
+    part: StoryPart,
+    start: TextPosition,
+    end: TextPosition,
+    point: bool
+}
+
+/// what to do with a `w:commentRangeStart` that is never closed
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RangeRecovery {
+    /// leave the range out of the result
+    Drop,
+    /// close the range at the end of the paragraph it begins in
+    CloseAtParagraphEnd,
+    /// close the range at the end of its part
+    CloseAtEnd
+}
+
+/// a problem found in the comment ranges of a document
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CommentDiagnostic {
+    /// a `w:commentRangeEnd` for a range that was not open
+    EndWithoutStart { id: usize, part: StoryPart, position: TextPosition },
+    /// a `w:commentRangeStart` that was never closed
+    StartWithoutEnd { id: usize, part: StoryPart, start: TextPosition }
+}
+
+impl fmt::Display for CommentDiagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CommentDiagnostic::EndWithoutStart { id, ref part, position } =>
+                write!(f, "{}: comment {} closed at {}:{}, but was not open", part, id, position.paragraph, position.offset),
+            CommentDiagnostic::StartWithoutEnd { id, ref part, start } =>
+                write!(f, "{}: comment {} opened at {}:{}, but was not closed", part, id, start.paragraph, start.offset)
+        }
+    }
+}
+
+/// the amount of context to take around a commented range
+pub enum ContextWindow {
+    /// up to N characters
+    Chars(usize),
+    /// up to N sentences, counting the sentence the range begins (or ends) in
+    Sentences(usize)
+}
+
+/// the text surrounding a commented range
+/// context never extends beyond the paragraph the range begins (or ends) in
+pub struct CommentContext {
+    before: String,
+    after: String
+}
+
+impl CommentContext {
+    /// the text preceding the range
+    pub fn before(&self) -> &str {
+        self.before.as_str()
+    }
+
+    /// the text following the range
+    pub fn after(&self) -> &str {
+        self.after.as_str()
+    }
+}
+
+/// a comment joined with the text it refers to
+/// comments and ranges that lack their counterpart are reported explicitly
+pub enum CommentRecord {
+    /// a comment with its body and the text it refers to
+    Anchored { comment: DocxComment, commented: DocxCommented },
+    /// a comment in word/comments.xml without a range in word/document.xml
+    MissingRange(DocxComment),
+    /// a range in word/document.xml without a comment in word/comments.xml
+    MissingBody(DocxCommented)
+}
+
+impl CommentRecord {
+    /// the comment body and its metadata, if present
+    pub fn comment(&self) -> Option<&DocxComment> {
+        match *self {
+            CommentRecord::Anchored { ref comment, .. } => Some(comment),
+            CommentRecord::MissingRange(ref comment) => Some(comment),
+            CommentRecord::MissingBody(_) => None
+        }
+    }
+
+    /// the text the comment refers to, if present
+    pub fn commented(&self) -> Option<&DocxCommented> {
+        match *self {
+            CommentRecord::Anchored { ref commented, .. } => Some(commented),
+            CommentRecord::MissingRange(_) => None,
+            CommentRecord::MissingBody(ref commented) => Some(commented)
+        }
+    }
+}
+
+/// a comment together with the replies to it
+/// as recorded in word/commentsExtended.xml
+pub struct CommentThread {
+    root: DocxComment,
+    replies: Vec<DocxComment>,
+    resolved: bool
+}
+
+impl CommentThread {
+    /// the comment that started the thread
+    pub fn root(&self) -> &DocxComment {
+        &self.root
+    }
+
+    /// the replies in order of appearance
+    pub fn replies(&self) -> &[DocxComment] {
+        &self.replies
+    }
+
+    /// true if a reviewer marked the thread as done
+    pub fn is_resolved(&self) -> bool {
+        self.resolved
+    }
+}
+
+/// an entry `<w15:commentEx>` of word/commentsExtended.xml
+struct DocxCommentEx {
+    para_id: String,
+    para_id_parent: Option<String>,
+    done: bool
 }
 
 pub trait Comment {
+    /// the runs of each paragraph concatenated, paragraphs separated by a newline
     fn text(&self) -> &str;
+    /// the paragraphs of the comment body
+    fn paragraphs(&self) -> &[CommentParagraph];
+    /// the comment body rendered as Markdown, keeping emphasis, hyperlinks and mentions
+    fn markdown(&self) -> String;
+    /// the reviewer that wrote the comment (`w:author`)
+    fn author(&self) -> Option<&str>;
+    /// the reviewer's initials (`w:initials`)
+    fn initials(&self) -> Option<&str>;
+    /// the time the comment was written (`w:date`), normalized to UTC
+    fn date(&self) -> Option<DateTime<Utc>>;
 }
 
-pub trait RangeId {
-    fn id(&self) -> usize;
+/// the text, part and positions of the range are those of `AnnotatedRange`
+pub trait Commented: AnnotatedRange {
+    /// true for a comment without range, anchored at a single position
+    fn is_point(&self) -> bool;
 }
 
 impl Comment for DocxComment {
     fn text(&self) -> &str {
         self.data.as_str()
     }
+
+    fn paragraphs(&self) -> &[CommentParagraph] {
+        &self.paragraphs
+    }
+
+    fn markdown(&self) -> String {
+        markdown(&self.paragraphs)
+    }
+
+    fn author(&self) -> Option<&str> {
+        self.author.as_deref()
+    }
+
+    fn initials(&self) -> Option<&str> {
+        self.initials.as_deref()
+    }
+
+    fn date(&self) -> Option<DateTime<Utc>> {
+        self.date
+    }
 }
 
 impl RangeId for DocxComment {
@@ -44,63 +225,173 @@ impl RangeId for DocxComment {
     }
 }
 
-pub trait ReadComments<T> {
+impl Commented for DocxCommented {
+    fn is_point(&self) -> bool {
+        self.point
+    }
+}
+
+impl AnnotatedRange for DocxCommented {
+    fn kind(&self) -> RangeKind {
+        RangeKind::Comment
+    }
+
+    fn label(&self) -> String {
+        self.id.to_string()
+    }
+
+    fn text(&self) -> &str {
+        self.data.as_str()
+    }
+
+    fn part(&self) -> &StoryPart {
+        &self.part
+    }
+
+    fn start(&self) -> TextPosition {
+        self.start
+    }
+
+    fn end(&self) -> TextPosition {
+        self.end
+    }
+}
+
+impl RangeId for DocxCommented {
+    fn id(&self) -> usize {
+        self.id
+    }
+}
+
+impl RangeId for CommentRecord {
+    fn id(&self) -> usize {
+        match *self {
+            CommentRecord::Anchored { ref comment, .. } => comment.id,
+            CommentRecord::MissingRange(ref comment) => comment.id,
+            CommentRecord::MissingBody(ref commented) => commented.id
+        }
+    }
+}
+
+pub trait ReadComments<T: MsDoc<T> + ReadComments<T>> {
     /// read the comment contents
-    fn open_comments<P: AsRef<Path>>(path: P) -> io::Result<Vec<DocxComment>>;
+    fn comments(&self) -> Result<Vec<DocxComment>>;
     /// read the contents of the regions referenced by comments
-    fn open_commented<P: AsRef<Path>>(path: P) -> io::Result<Vec<DocxComment>>;
+    fn commented(&self) -> Result<Vec<DocxCommented>>;
+    /// like `commented`, but recover ranges that are never closed
+    /// and report malformed ranges to the caller
+    fn commented_with_diagnostics(&self, recovery: RangeRecovery) -> Result<(Vec<DocxCommented>,Vec<CommentDiagnostic>)>;
+    /// read the contents of the regions referenced by comments, together with their surroundings
+    fn commented_in_context(&self, window: ContextWindow) -> Result<Vec<(DocxCommented,CommentContext)>>;
+    /// read the comments joined with the regions they refer to, one record per comment id
+    fn comment_records(&self) -> Result<Vec<CommentRecord>>;
+    /// read the comments grouped into threads of root comment and replies
+    fn comment_threads(&self) -> Result<Vec<CommentThread>>;
+
+    /// open the file and read the comment contents
+    fn open_comments<P: AsRef<Path>>(path: P) -> Result<Vec<DocxComment>> {
+        T::open(path)?.comments()
+    }
+    /// open the file and read the contents of the regions referenced by comments
+    fn open_commented<P: AsRef<Path>>(path: P) -> Result<Vec<DocxCommented>> {
+        T::open(path)?.commented()
+    }
+    fn open_commented_with_diagnostics<P: AsRef<Path>>(path: P, recovery: RangeRecovery) -> Result<(Vec<DocxCommented>,Vec<CommentDiagnostic>)> {
+        T::open(path)?.commented_with_diagnostics(recovery)
+    }
+    fn open_commented_in_context<P: AsRef<Path>>(path: P, window: ContextWindow) -> Result<Vec<(DocxCommented,CommentContext)>> {
+        T::open(path)?.commented_in_context(window)
+    }
+    fn open_comment_records<P: AsRef<Path>>(path: P) -> Result<Vec<CommentRecord>> {
+        T::open(path)?.comment_records()
+    }
+    fn open_comment_threads<P: AsRef<Path>>(path: P) -> Result<Vec<CommentThread>> {
+        T::open(path)?.comment_threads()
+    }
 }
 
 impl ReadComments<Docx> for Docx {
-    
-    fn open_comments<P: AsRef<Path>>(path: P) -> io::Result<Vec<DocxComment>> {
-        let file = File::open(path.as_ref())?;
-        let mut archive = ZipArchive::new(file)?;
 
-        let mut xml_data = String::new();
+    fn comments(&self) -> Result<Vec<DocxComment>> {
+        let (name, xml_data) = match self.related_part(REL_COMMENTS)? {
+            Some(part) => part,
+            // documents without comments lack this part
+            None => return Ok(vec![])
+        };
 
-        for i in 0..archive.len() {
-            let mut c_file = archive.by_index(i).unwrap();
-            if c_file.name() == "word/comments.xml" {
-                c_file.read_to_string(&mut xml_data);
-                break
-            }
-        }
-        // post: if no document.xml was found, then no data was read to buffer
-        
-        if xml_data.len() == 0 {
-            //return Err(io::Error::new(io::ErrorKind::NotFound,".docx invalid: did not contain word/comments.xml".to_string()))
-            // documents without comments may lack this file
-            return Ok(vec![]);
-        }
+        // hyperlink targets of the comments
+        let links: HashMap<String,String> = self.relationships(&name)?.unwrap_or_default().into_iter()
+            .map(|r| (r.id, r.target))
+            .collect();
 
         let xml_reader = Reader::from_str(xml_data.as_ref());
-        read_comments(xml_reader)
+        read_comments(xml_reader, &links).map_err(|e| e.in_part(&name))
     }
 
     /// collect the commented areas per id
     /// consider that comment ranges may overlap
-    fn open_commented<P: AsRef<Path>>(path: P) -> io::Result<Vec<DocxComment>> {    
-        let file = File::open(path.as_ref())?;
-        let mut archive = ZipArchive::new(file)?;
+    /// ranges in headers, footers, footnotes and endnotes follow those of the main document
+    /// malformed ranges are left out
+    fn commented(&self) -> Result<Vec<DocxCommented>> {
+        let (res, _) = self.commented_with_diagnostics(RangeRecovery::Drop)?;
+        Ok(res)
+    }
 
-        let mut xml_data = String::new();
+    fn commented_with_diagnostics(&self, recovery: RangeRecovery) -> Result<(Vec<DocxCommented>,Vec<CommentDiagnostic>)> {
+        let mut res = Vec::new();
+        let mut diagnostics = Vec::new();
+        walk_story_parts(self, |part, walker| {
+            let mut extractor = CommentedExtractor::new(part, recovery);
+            let walk = walker(&mut [&mut extractor])?;
+            let (commented, part_diagnostics) = extractor.finish(&walk);
+            res.extend(commented);
+            diagnostics.extend(part_diagnostics);
+            Ok(())
+        })?;
+        Ok((res, diagnostics))
+    }
 
-        for i in 0..archive.len() {
-            let mut c_file = archive.by_index(i).unwrap();
-            if c_file.name() == "word/document.xml" {
-                c_file.read_to_string(&mut xml_data);
-                break
-            }
-        }
-        // post: if no document.xml was found, then no data was read to buffer
-        
-        if xml_data.len() == 0 {
-            return Err(io::Error::new(io::ErrorKind::NotFound,".docx invalid: did not contain word/document.xml".to_string()))
+    fn commented_in_context(&self, window: ContextWindow) -> Result<Vec<(DocxCommented,CommentContext)>> {
+        let mut res = Vec::new();
+        walk_story_parts(self, |part, walker| {
+            let mut extractor = CommentedExtractor::new(part, RangeRecovery::Drop);
+            let mut text = ParagraphExtractor::default();
+            let walk = walker(&mut [&mut extractor, &mut text])?;
+            let (commented, _) = extractor.finish(&walk);
+            let paragraphs = text.finish();
+            res.extend(commented.into_iter()
+                .map(|c| {
+                    let context = comment_context(&paragraphs, &c, &window);
+                    (c, context)
+                }));
+            Ok(())
+        })?;
+        Ok(res)
+    }
+
+    /// records are in order of word/comments.xml,
+    /// followed by ranges without comment in order of appearance
+    fn comment_records(&self) -> Result<Vec<CommentRecord>> {
+        let comments = self.comments()?;
+        let commented = self.commented()?;
+        Ok(join_records(comments, commented))
+    }
+
+    /// documents written by older versions of Word (or other producers) lack
+    /// word/commentsExtended.xml; then every comment is its own unresolved thread
+    fn comment_threads(&self) -> Result<Vec<CommentThread>> {
+        let comments = self.comments()?;
+        if comments.is_empty() {
+            return Ok(vec![]);
         }
 
-        let xml_reader = Reader::from_str(xml_data.as_ref());
-        read_commented(xml_reader)
+        let extended = match self.related_part(REL_COMMENTS_EXTENDED)? {
+            Some((name, extended_data)) => read_comments_extended(Reader::from_str(extended_data.as_ref()))
+                .map_err(|e| e.in_part(&name))?,
+            None => vec![]
+        };
+
+        Ok(join_threads(comments, extended))
     }
 }
 
@@ -131,59 +422,299 @@ where A: for<'r> BufRead
         f(xml_reader)
 }
 */
-    
-fn read_comments<B: BufRead>(mut xml_reader: Reader<B>) -> io::Result<Vec<DocxComment>> {
+
+/// parse a `w:date` attribute value (ST_DateTime, i.e. ISO 8601)
+/// Word usually writes UTC ("2019-01-17T11:57:02Z"), but some producers
+/// omit the zone designator; such dates are taken as UTC
+fn parse_date(date_str: &str) -> Option<DateTime<Utc>> {
+    DateTime::<FixedOffset>::parse_from_rfc3339(date_str)
+        .map(|d| d.with_timezone(&Utc))
+        .or_else(|_| NaiveDateTime::parse_from_str(date_str, "%Y-%m-%dT%H:%M:%S%.f").map(|d| d.and_utc()))
+        .ok()
+}
+
+/// `links` maps relationship ids to hyperlink targets
+fn read_comments<B: BufRead>(xml_reader: Reader<B>, links: &HashMap<String,String>) -> Result<Vec<DocxComment>> {
+    let mut xml_reader = NsReader::new(xml_reader);
 
     let mut buf = Vec::new();
-    let mut txt = Vec::new(); // a range
+    let mut body = CommentBodyBuilder::new();
     let mut par = Vec::new();
 
     let mut a_id: isize = -1;
+    let mut author = None;
+    let mut initials = None;
+    let mut date = None;
+    let mut para_id = None;
 
     let mut to_read = false;
     loop {
         match xml_reader.read_event(&mut buf) {
-            Ok(Event::Start(ref e)) => {
-                match e.name() {
-                    b"w:comment" => { // entered a paragraph
-                        let id_cstr = e.attributes().find(|a| a.as_ref().unwrap().key==b"w:id" ).map(|a| a.unwrap().value ).expect("malformed word/comments.xml: missing attribute 'w:id' on tag 'comment'");
-                        a_id = String::from_utf8(id_cstr.to_vec()).unwrap().parse::<isize>().expect("comment attribute 'w:id' was not a number");
+            Ok((Ns::W, Event::Start(ref e))) => {
+                match e.local_name() {
+                    b"comment" => { // entered a comment
+                        a_id = e.get_attr_as::<usize, _>(&xml_reader, Ns::W, b"id")? as isize;
+                        // empty values are written by producers that do not know the reviewer
+                        author = e.get_attr_opt(&xml_reader, Ns::W, b"author")?.filter(|s| !s.is_empty());
+                        initials = e.get_attr_opt(&xml_reader, Ns::W, b"initials")?.filter(|s| !s.is_empty());
+                        date = e.get_attr_opt(&xml_reader, Ns::W, b"date")?.and_then(|s| parse_date(&s));
+                    }
+                    , b"p" => { // only the id of the last paragraph is kept
+                        para_id = e.get_attr_opt(&xml_reader, Ns::W14, b"paraId")?;
+                        body.start_paragraph();
+                    }
+                    , b"hyperlink" => {
+                        let link = match (e.get_attr_opt(&xml_reader, Ns::R, b"id")?, e.get_attr_opt(&xml_reader, Ns::W, b"anchor")?) {
+                            (Some(ref r_id), _) => links.get(r_id).map(|t| CommentLink::Url(t.clone())),
+                            (None, Some(anchor)) => Some(CommentLink::Bookmark(anchor)),
+                            (None, None) => None
+                        };
+                        body.start_hyperlink(link);
+                    }
+                    , b"r" => {
+                        body.start_run();
                     }
-                    , b"w:t" => { // entered a text section
+                    , b"t" => { // entered a text section
                         to_read = true;
                     }
                     , _ => ()
                 }
             }
-            , Ok(Event::End(ref e)) => {
-                match e.name() {
-                    b"w:comment" => { // exited a paragraph
-                        let comment = DocxComment { id: a_id as usize, data: txt.join("\n") }; // join ranges
+            , Ok((Ns::W, Event::Empty(ref e))) => {
+                match e.local_name() {
+                    // direct formatting; only the absence of w:val means "on"
+                    b"b" if body.in_run() => body.set_bold(e.get_attr_on_off(&xml_reader, Ns::W, b"val")?.unwrap_or(true))
+                    , b"i" if body.in_run() => body.set_italic(e.get_attr_on_off(&xml_reader, Ns::W, b"val")?.unwrap_or(true))
+                    , b"p" => { // empty paragraph
+                        para_id = e.get_attr_opt(&xml_reader, Ns::W14, b"paraId")?;
+                        body.start_paragraph();
+                        body.end_paragraph();
+                    }
+                    // w:tab also appears as tab stop definition in w:pPr
+                    , b"tab" if body.in_run() => body.push_text("\t")
+                    , b"br" | b"cr" if body.in_run() => body.push_text("\n")
+                    , _ => ()
+                }
+            }
+            , Ok((Ns::W, Event::End(ref e))) => {
+                match e.local_name() {
+                    b"comment" => { // exited a comment
+                        let paragraphs = body.finish();
+                        let comment = DocxComment {
+                            id: a_id as usize,
+                            author: author.take(),
+                            initials: initials.take(),
+                            date: date.take(),
+                            data: plain_text(&paragraphs),
+                            paragraphs,
+                            para_id: para_id.take()
+                        };
                         par.push(comment);
                         a_id = -1;
-                        txt = Vec::new();
                     }
+                    , b"p" => body.end_paragraph()
+                    , b"hyperlink" => body.end_hyperlink()
+                    , b"r" => body.end_run()
+                    , b"t" => to_read = false
                     , _ => ()
                 }
             }
-            , Ok(Event::Text(e)) => {
+            , Ok((_, Event::Text(e))) => {
                 if to_read {
-                    txt.push(e.unescape_and_decode(&xml_reader).unwrap());
+                    body.push_text(&xml_reader.text(&e)?);
                     to_read = false;
                 }
             }
-            , Ok(Event::Eof) => break
+            , Ok((_, Event::Eof)) => break
             , Ok(_) => ()
-            , Err(e) => panic!("Error at position {}: {:?}", xml_reader.buffer_position(), e),
+            , Err(e) => return Err(Error::xml(xml_reader.buffer_position(), e)),
         }
+        buf.clear();
     }
 
-    // could also panic
-    if txt.len() > 0 {
-        eprintln!("After reading all comments, buffer still contained: {}", txt.join(""));
+    // text outside of w:comment is not part of any comment
+    Ok(par)
+}
+
+/// read the `<w15:commentEx>` entries of word/commentsExtended.xml
+fn read_comments_extended<B: BufRead>(xml_reader: Reader<B>) -> Result<Vec<DocxCommentEx>> {
+    let mut xml_reader = NsReader::new(xml_reader);
+
+    let mut buf = Vec::new();
+    let mut res = Vec::new();
+
+    loop {
+        match xml_reader.read_event(&mut buf) {
+            Ok((Ns::W15, Event::Empty(ref e))) | Ok((Ns::W15, Event::Start(ref e))) if e.local_name() == b"commentEx" => {
+                let comment_ex = DocxCommentEx {
+                    para_id: e.get_attr(&xml_reader, Ns::W15, b"paraId")?,
+                    para_id_parent: e.get_attr_opt(&xml_reader, Ns::W15, b"paraIdParent")?,
+                    done: e.get_attr_on_off(&xml_reader, Ns::W15, b"done")?.unwrap_or(false)
+                };
+                res.push(comment_ex);
+            }
+            , Ok((_, Event::Eof)) => break
+            , Ok(_) => ()
+            , Err(e) => return Err(Error::xml(xml_reader.buffer_position(), e)),
+        }
+        buf.clear();
     }
 
-    Ok(par)
+    Ok(res)
+}
+
+/// group comments into threads
+/// Word links every reply to the root of its thread, but replies to replies
+/// are followed up to the root anyway
+fn join_threads(comments: Vec<DocxComment>, extended: Vec<DocxCommentEx>) -> Vec<CommentThread> {
+    let extended: HashMap<&str,&DocxCommentEx> = extended.iter()
+        .map(|ex| (ex.para_id.as_str(), ex))
+        .collect();
+
+    let find_root = |para_id: &str| -> String {
+        let mut cur = para_id;
+        // bounded walk, guards against cyclic parent links
+        for _ in 0..extended.len() {
+            match extended.get(cur).and_then(|ex| ex.para_id_parent.as_ref()) {
+                Some(parent) if extended.contains_key(parent.as_str()) => cur = parent.as_str(),
+                _ => break
+            }
+        }
+        cur.to_string()
+    };
+
+    let mut threads: Vec<CommentThread> = Vec::new();
+    let mut thread_pos: HashMap<String,usize> = HashMap::new(); // root paraId -> index in threads
+    let mut orphans = Vec::new(); // replies that appear before their root
+
+    for comment in comments {
+        let root_para_id = comment.para_id.as_ref().map(|p| find_root(p));
+        let is_reply = match (&comment.para_id, &root_para_id) {
+            (Some(p), Some(r)) => p != r,
+            _ => false
+        };
+        if is_reply {
+            let root_para_id = root_para_id.unwrap();
+            match thread_pos.get(&root_para_id) {
+                Some(&pos) => threads[pos].replies.push(comment),
+                None => orphans.push((root_para_id, comment))
+            }
+        } else {
+            let resolved = comment.para_id.as_ref()
+                .and_then(|p| extended.get(p.as_str()))
+                .map(|ex| ex.done)
+                .unwrap_or(false);
+            if let Some(p) = root_para_id {
+                thread_pos.insert(p, threads.len());
+            }
+            threads.push(CommentThread { root: comment, replies: vec![], resolved });
+        }
+    }
+
+    for (root_para_id, comment) in orphans {
+        match thread_pos.get(&root_para_id) {
+            Some(&pos) => threads[pos].replies.push(comment),
+            // the root comment was deleted, keep the reply as its own thread
+            None => threads.push(CommentThread { root: comment, replies: vec![], resolved: false })
+        }
+    }
+
+    threads
+}
+
+/// cut the context of a range from the paragraphs it begins and ends in
+fn comment_context(paragraphs: &[String], commented: &DocxCommented, window: &ContextWindow) -> CommentContext {
+    // paragraphs are followed by a newline in the plain text
+    let par_start = |n: usize| -> usize {
+        paragraphs.iter().take(n).map(|p| p.chars().count() + 1).sum()
+    };
+    let chars_of = |n: usize| -> Vec<char> {
+        paragraphs.get(n).map(|p| p.chars().collect()).unwrap_or_default()
+    };
+
+    let start_chars = chars_of(commented.start.paragraph);
+    let end_chars = chars_of(commented.end.paragraph);
+    // relative to the paragraph; ranges that begin or end between paragraphs are clamped
+    let start = commented.start.offset.saturating_sub(par_start(commented.start.paragraph)).min(start_chars.len());
+    let end = commented.end.offset.saturating_sub(par_start(commented.end.paragraph)).min(end_chars.len());
+
+    let (from, to) = match *window {
+        ContextWindow::Chars(n) => (start.saturating_sub(n), end.saturating_add(n).min(end_chars.len())),
+        ContextWindow::Sentences(0) => (start, end),
+        ContextWindow::Sentences(n) => {
+            let starts_before = sentence_starts(&start_chars);
+            let starts_after = sentence_starts(&end_chars);
+            // index of the sentence containing the first (last) character of the range
+            let k = starts_before.iter().rposition(|&i| i <= start).unwrap_or(0);
+            let m = starts_after.iter().rposition(|&i| i < end.max(1)).unwrap_or(0);
+            let from = starts_before[k.saturating_sub(n - 1)];
+            let mut to = starts_after.get(m.saturating_add(n)).cloned().unwrap_or(end_chars.len());
+            while to > end && end_chars[to - 1].is_whitespace() {
+                to -= 1;
+            }
+            (from, to)
+        }
+    };
+
+    CommentContext {
+        before: start_chars[from..start].iter().collect(),
+        after: end_chars[end..to.max(end)].iter().collect()
+    }
+}
+
+/// character indices where sentences begin
+/// a sentence ends with '.', '!' or '?' (possibly followed by closing quotes or brackets)
+/// and whitespace
+fn sentence_starts(chars: &[char]) -> Vec<usize> {
+    let mut res = vec![0];
+    let mut terminated = false; // seen a terminator
+    let mut spaced = false; // seen whitespace after the terminator
+    for (i, c) in chars.iter().enumerate() {
+        if c.is_whitespace() {
+            spaced = terminated;
+        } else if terminated && spaced {
+            res.push(i);
+            terminated = is_sentence_terminator(*c);
+            spaced = false;
+        } else if is_sentence_terminator(*c) {
+            terminated = true;
+        } else if !(terminated && is_closing(*c)) {
+            terminated = false;
+        }
+    }
+    res
+}
+
+fn is_sentence_terminator(c: char) -> bool {
+    c == '.' || c == '!' || c == '?' || c == '…'
+}
+
+fn is_closing(c: char) -> bool {
+    c == '"' || c == '\'' || c == ')' || c == ']' || c == '”' || c == '’' || c == '»'
+}
+
+fn join_records(comments: Vec<DocxComment>, commented: Vec<DocxCommented>) -> Vec<CommentRecord> {
+    let mut ranges: HashMap<usize,DocxCommented> = HashMap::with_capacity(commented.len());
+    let mut order = Vec::with_capacity(commented.len()); // ids in document order
+    for range in commented {
+        order.push(range.id);
+        ranges.entry(range.id).or_insert(range); // ids are unique, keep the first on collision
+    }
+
+    let mut res: Vec<CommentRecord> = comments.into_iter()
+        .map(|comment| match ranges.remove(&comment.id) {
+            Some(commented) => CommentRecord::Anchored { comment, commented },
+            None => CommentRecord::MissingRange(comment)
+        })
+        .collect();
+
+    for id in order {
+        if let Some(commented) = ranges.remove(&id) {
+            res.push(CommentRecord::MissingBody(commented));
+        }
+    }
+
+    res
 }
 
 /*
@@ -200,107 +731,126 @@ impl<'a> GetAttr for BytesStart<'a> {
 }
 */
 
+/// a range of word/document.xml that is open while walking over the xml file
+struct OpenRange {
+    start: TextPosition,
+    data: String,
+    // end position and text length at the end of the paragraph the range begins in,
+    // used to recover a range that is never closed
+    par_end: Option<(TextPosition,usize)>
+}
+
 /// Word allows comments to overlap on the text,
 /// this means that any given text can be quoted by multiple comments.
-/// The 'comment_ranges_open' collects the text ranges
+/// The 'open' ranges collect the text
 /// for all currently open comments while walking over the xml file
-fn read_commented<B: BufRead>(mut xml_reader: Reader<B>) -> io::Result<Vec<DocxComment>> {
+/// Positions are counted like the plain text of `Docx::open`:
+/// the text of every `w:t`, and a newline at the end of each paragraph
+pub(crate) struct CommentedExtractor<'a> {
+    part: &'a StoryPart,
+    recovery: RangeRecovery,
+    res: Vec<DocxCommented>,
+    diagnostics: Vec<CommentDiagnostic>,
+    // map from comment_id -> start position and buffer
+    // used for collecting text in multiple open comments
+    open: HashMap<usize,OpenRange>,
+    // ids that had a w:commentRangeStart; references to other ids are point comments
+    seen: HashSet<usize>,
+    // point comments wait for the end of their paragraph
+    points_open: Vec<(usize,TextPosition)>,
+    par_txt: String // text of the current paragraph
+}
 
-    let mut buf = Vec::new();
-    let mut txt = Vec::new(); // collection of text ranges within a contiguous comment range
-    let mut par = Vec::new();
+impl<'a> CommentedExtractor<'a> {
+    pub fn new(part: &'a StoryPart, recovery: RangeRecovery) -> CommentedExtractor<'a> {
+        CommentedExtractor {
+            part,
+            recovery,
+            res: Vec::new(),
+            diagnostics: Vec::new(),
+            open: HashMap::new(),
+            seen: HashSet::new(),
+            points_open: Vec::new(),
+            par_txt: String::new()
+        }
+    }
 
-    // map from comment_id -> buffer
-    // used for collecting text in multiple open comments
-    let mut comment_ranges_open: HashMap<usize,String> = HashMap::new();
+    /// the ranges in order of their end, then those that were not closed
+    pub fn finish(mut self, walk: &Walk) -> (Vec<DocxCommented>,Vec<CommentDiagnostic>) {
+        // ranges that were not closed, in order of appearance
+        let mut dangling: Vec<(usize,OpenRange)> = self.open.drain().collect();
+        dangling.sort_by_key(|&(a_id, ref rng)| (rng.start, a_id));
+        let end = walk.end();
+        for (a_id, mut rng) in dangling {
+            self.diagnostics.push(CommentDiagnostic::StartWithoutEnd { id: a_id, part: self.part.clone(), start: rng.start });
+            let end = match (self.recovery, rng.par_end) {
+                (RangeRecovery::Drop, _) => continue,
+                (RangeRecovery::CloseAtParagraphEnd, Some((par_end, len))) => {
+                    rng.data.truncate(len);
+                    par_end
+                }
+                _ => end
+            };
+            self.res.push(DocxCommented{ id: a_id, data: rng.data, part: self.part.clone(), start: rng.start, end, point: false });
+        }
 
-    fn attr_id(event: &BytesStart) -> usize {
-        //let id_cstr = event.attributes().find(|a| a.as_ref().unwrap().key==b"w:id" ).map(|a| a.unwrap().value ).expect("malformed word/comments.xml: missing attribute 'w:id' on tag 'comment'");
-        //let id_str = String::from_utf8(id_cstr.to_vec()).unwrap();
-        let id_str = event.get_attr(b"w:id");
-        let a_id = id_str.parse::<usize>().expect("comment attribute 'w:id' was not a number");
-        a_id
+        (self.res, self.diagnostics)
     }
+}
 
-    let mut n_par = 0; // count paragraphs
-    let mut n_r   = 0; // count ranges (total, spanning over paragraphs)
+impl<'a> Visitor for CommentedExtractor<'a> {
+    fn paragraph_start(&mut self, _walk: &Walk) {
+        self.par_txt.clear();
+    }
 
-    let mut to_read = false;
-    loop {
-        match xml_reader.read_event(&mut buf) {
-            Ok(Event::Start(ref e)) => {
-                match e.name() {
-                    b"w:p" => {
-                        n_par += 1;
-                    }
-                    , b"w:r" => {
-                        n_r += 1;
-                    }
-                    , b"w:t" => { // entered a text section
-                        to_read = comment_ranges_open.len() > 0;
-                    }
-                    , _ => ()
-                }
+    fn paragraph_end(&mut self, walk: &Walk) {
+        let par_end = walk.end();
+        for rng in self.open.values_mut() {
+            if rng.par_end.is_none() && rng.start.paragraph == par_end.paragraph {
+                rng.par_end = Some((par_end, rng.data.len()));
             }
-            , Ok(Event::Empty(ref e)) => {
-                match e.name() {
-                    b"w:commentRangeStart" => { // begin a new commented block
-                        // push existing txt buffer to currently open ranges
-                        comment_ranges_open.values_mut()
-                            .for_each(|str_i| str_i.push_str(&txt.join("")) );
-                        txt = Vec::new();
-                        /*
-                        for (_k,str_i) in comment_ranges_open.iter_mut() {
-                            str_i.push_str(&txt.join("")); // append to string
-                        }*/
-                        // open a new comment range
-                        let a_id = attr_id(e);
-                        comment_ranges_open.insert(a_id, String::new());
+            // the range goes on past the paragraph break, like its positions
+            rng.data.push('\n');
+        }
+        for (a_id, pos) in self.points_open.drain(..) {
+            let comment = DocxCommented{ id: a_id, data: self.par_txt.clone(), part: self.part.clone(), start: pos, end: pos, point: true };
+            self.res.push(comment);
+        }
+    }
 
+    fn text(&mut self, text: &str, _walk: &Walk) {
+        self.par_txt.push_str(text);
+        // push text to currently open ranges
+        for rng in self.open.values_mut() {
+            rng.data.push_str(text);
+        }
+    }
+
+    fn range_marker(&mut self, marker: RangeMarker, walk: &Walk) {
+        match marker {
+            RangeMarker::CommentStart(a_id) => { // begin a new commented block
+                // between paragraphs, the range starts with the next paragraph
+                self.open.insert(a_id, OpenRange { start: walk.start(), data: String::new(), par_end: None });
+                self.seen.insert(a_id);
+            }
+            , RangeMarker::CommentEnd(a_id) => { // end one of the currently running comments
+                // between paragraphs, the range ends with the previous paragraph
+                let end = walk.end();
+                match self.open.remove(&a_id) {
+                    Some(rng) => {
+                        let comment = DocxCommented{ id: a_id, data: rng.data, part: self.part.clone(), start: rng.start, end, point: false };
+                        self.res.push(comment);
                     }
-                    , b"w:commentRangeEnd" =>  { // end one of the currently running comments
-                        // push existing txt buffer to currently open ranges
-                        for (_k,str_i) in comment_ranges_open.iter_mut() {
-                            str_i.push_str(&txt.join("")); // append to string
-                        }
-                        txt = Vec::new();
-                        // move a currently open range to result
-                        let a_id = attr_id(e);
-                        match comment_ranges_open.remove(&a_id) {
-                            Some(rng) => {
-                                let comment = DocxComment{ id: a_id, data: rng };
-                                par.push(comment);
-                            }
-                            // we could also panic
-                            , None => { eprintln!("Malformed word/document.xml: comment {} closed, but was not open", a_id); }
-                        }
-                        
-                    }
-                    ,_=>()
+                    , None => self.diagnostics.push(CommentDiagnostic::EndWithoutStart { id: a_id, part: self.part.clone(), position: end })
                 }
             }
-            , Ok(Event::Text(e)) => {
-                if to_read {
-                    txt.push(e.unescape_and_decode(&xml_reader).unwrap());
-                    to_read = false;
-                }
+            // the comment mark; usually follows w:commentRangeEnd
+            , RangeMarker::CommentReference(a_id) if !self.seen.contains(&a_id) && walk.in_paragraph() => {
+                self.points_open.push((a_id, walk.end()));
             }
-            , Ok(Event::Eof) => break
-            , Ok(_) => ()
-            , Err(e) => panic!("Error at position {}: {:?}", xml_reader.buffer_position(), e),
+            , _ => ()
         }
     }
-
-    // could also panic
-    if txt.len() > 0 {
-        eprintln!("After reading all comments, buffer still contained: {}", txt.join(""));
-    }
-    if comment_ranges_open.len() > 0 {
-        eprintln!("After reading all comments, {} comments were not closed", comment_ranges_open.len() );
-        // TODO: close the malformed comments
-    }
-
-    Ok(par)
 }
 
 // TODO: do analysis for "Dieser Begriff soll in Abschnitt 2 bis 5 erwähnt werden"
@@ -332,4 +882,281 @@ mod tests {
 
     // TODO: write a test for extracting highlighted parts from a file
 }
-*/
\ No newline at end of file
+*/
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use docx_walk::walk_part;
+
+    const COMMENTS: &str = r#"<w:comments xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"
+        xmlns:w14="http://schemas.microsoft.com/office/word/2010/wordml">
+        <w:comment w:id="0"><w:p w14:paraId="A"><w:r><w:t>root</w:t></w:r></w:p></w:comment>
+        <w:comment w:id="1"><w:p w14:paraId="B"><w:r><w:t>reply</w:t></w:r></w:p></w:comment>
+        <w:comment w:id="2"><w:p w14:paraId="C"><w:r><w:t>reply to reply</w:t></w:r></w:p></w:comment>
+        <w:comment w:id="3"><w:p w14:paraId="E"><w:r><w:t>reply to deleted</w:t></w:r></w:p></w:comment>
+        <w:comment w:id="4"><w:p w14:paraId="F"><w:r><w:t>resolved</w:t></w:r></w:p></w:comment>
+        <w:comment w:id="5"><w:p w14:paraId="H"><w:r><w:t>reply before root</w:t></w:r></w:p></w:comment>
+        <w:comment w:id="6"><w:p w14:paraId="G"><w:r><w:t>late root</w:t></w:r></w:p></w:comment>
+        <w:comment w:id="7"><w:p w14:paraId="X"/></w:comment>
+        <w:comment w:id="8"><w:p w14:paraId="Y"/></w:comment>
+    </w:comments>"#;
+
+    const COMMENTS_EXTENDED: &str = r#"<w15:commentsEx xmlns:w15="http://schemas.microsoft.com/office/word/2012/wordml">
+        <w15:commentEx w15:paraId="A" w15:done="0"/>
+        <w15:commentEx w15:paraId="B" w15:paraIdParent="A" w15:done="0"/>
+        <w15:commentEx w15:paraId="C" w15:paraIdParent="B" w15:done="0"/>
+        <w15:commentEx w15:paraId="D" w15:done="0"/>
+        <w15:commentEx w15:paraId="E" w15:paraIdParent="D" w15:done="0"/>
+        <w15:commentEx w15:paraId="F" w15:done="1"/>
+        <w15:commentEx w15:paraId="H" w15:paraIdParent="G"/>
+        <w15:commentEx w15:paraId="G"/>
+        <w15:commentEx w15:paraId="X" w15:paraIdParent="Y"/>
+        <w15:commentEx w15:paraId="Y" w15:paraIdParent="X"/>
+    </w15:commentsEx>"#;
+
+    #[test]
+    fn wordprocessingml_under_any_prefix() {
+        // the default namespace for elements, another prefix for attributes
+        let xml = r#"<comments xmlns="http://schemas.openxmlformats.org/wordprocessingml/2006/main"
+            xmlns:x="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+            <comment x:id="3" x:author="Ann"><p><r><t>default</t></r></p></comment>
+            <x:comment x:id="4"><x:p><x:r><x:t>prefixed</x:t></x:r></x:p></x:comment>
+        </comments>"#;
+        let comments = read_comments(Reader::from_str(xml), &HashMap::new()).unwrap();
+        let read: Vec<(usize, &str)> = comments.iter().map(|c| (c.id(), c.text())).collect();
+        assert_eq!(read, vec![(3, "default"), (4, "prefixed")]);
+        assert_eq!(comments[0].author(), Some("Ann"));
+    }
+
+    #[test]
+    fn runs_join_and_paragraphs_break() {
+        let xml = r#"<w:comments xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+            <w:comment w:id="0"><w:p><w:r><w:annotationRef/></w:r><w:r><w:t xml:space="preserve">one </w:t></w:r><w:r><w:t>run</w:t></w:r><w:r><w:t>s</w:t></w:r></w:p>
+            <w:p><w:r><w:t>two</w:t></w:r><w:r><w:t xml:space="preserve"> par</w:t></w:r></w:p></w:comment>
+        </w:comments>"#;
+        let comments = read_comments(Reader::from_str(xml), &HashMap::new()).unwrap();
+        assert_eq!(comments[0].text(), "one runs\ntwo par");
+        let runs: Vec<usize> = comments[0].paragraphs().iter().map(|p| p.runs().len()).collect();
+        assert_eq!(runs, vec![3, 2]);
+    }
+
+    #[test]
+    fn dates_are_normalized_to_utc() {
+        let utc = |s: &str| parse_date(s).map(|d| d.to_rfc3339());
+        assert_eq!(utc("2019-01-17T11:57:02Z"), Some("2019-01-17T11:57:02+00:00".to_string()));
+        assert_eq!(utc("2019-01-17T11:57:02+02:00"), Some("2019-01-17T09:57:02+00:00".to_string()));
+        assert_eq!(utc("2019-01-17T11:57:02-05:30"), Some("2019-01-17T17:27:02+00:00".to_string()));
+        // without zone designator, taken as UTC
+        assert_eq!(utc("2019-01-17T11:57:02"), Some("2019-01-17T11:57:02+00:00".to_string()));
+        assert_eq!(utc("2019-01-17T11:57:02.5"), Some("2019-01-17T11:57:02.500+00:00".to_string()));
+        assert_eq!(utc("17.01.2019"), None);
+    }
+
+    fn threads() -> Vec<CommentThread> {
+        let comments = read_comments(Reader::from_str(COMMENTS), &HashMap::new()).unwrap();
+        let extended = read_comments_extended(Reader::from_str(COMMENTS_EXTENDED)).unwrap();
+        join_threads(comments, extended)
+    }
+
+    fn thread_ids(thread: &CommentThread) -> (usize, Vec<usize>) {
+        (thread.root().id(), thread.replies().iter().map(|r| r.id()).collect())
+    }
+
+    #[test]
+    fn replies_join_the_thread_of_their_root() {
+        let threads = threads();
+        // a reply to a reply belongs to the first comment
+        assert_eq!(thread_ids(&threads[0]), (0, vec![1, 2]));
+        // a reply that comes before its root is added to it
+        let late = threads.iter().find(|t| t.root().id() == 6).unwrap();
+        assert_eq!(thread_ids(late), (6, vec![5]));
+    }
+
+    #[test]
+    fn reply_to_deleted_root_is_its_own_thread() {
+        let threads = threads();
+        let orphan = threads.iter().find(|t| t.root().id() == 3).unwrap();
+        assert!(orphan.replies().is_empty());
+        assert!(!orphan.is_resolved());
+    }
+
+    #[test]
+    fn resolved_threads() {
+        let threads = threads();
+        let resolved: Vec<usize> = threads.iter().filter(|t| t.is_resolved()).map(|t| t.root().id()).collect();
+        assert_eq!(resolved, vec![4]);
+    }
+
+    #[test]
+    fn cyclic_parents_keep_every_comment() {
+        let threads = threads();
+        let mut ids: Vec<usize> = threads.iter()
+            .flat_map(|t| Some(t.root().id()).into_iter().chain(t.replies().iter().map(|r| r.id())))
+            .collect();
+        ids.sort();
+        assert_eq!(ids, (0..9).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn records_report_missing_counterparts() {
+        let comments = read_comments(Reader::from_str(COMMENTS), &HashMap::new()).unwrap();
+        let range = |id: usize| DocxCommented { id, data: "text".to_string(), part: StoryPart::Main,
+            start: TextPosition::default(), end: TextPosition { paragraph: 0, offset: 4 }, point: false };
+        let records = join_records(comments, vec![range(1), range(42)]);
+        assert_eq!(records.len(), 10);
+        assert!(records[0].commented().is_none());
+        assert_eq!(records[0].comment().map(|c| c.id()), Some(0));
+        assert_eq!(records[1].commented().map(|c| c.text()), Some("text"));
+        assert_eq!(records[1].comment().map(|c| c.text()), Some("reply"));
+        // ranges without comment come last
+        assert!(records[9].comment().is_none());
+        assert_eq!(records[9].commented().map(|c| c.id()), Some(42));
+    }
+
+    #[test]
+    fn sentences_start_after_terminator_and_space() {
+        let starts = |s: &str| sentence_starts(&s.chars().collect::<Vec<char>>());
+        assert_eq!(starts(""), vec![0]);
+        assert_eq!(starts("Hi. Bye"), vec![0, 4]);
+        // closing quotes belong to the sentence they close
+        assert_eq!(starts("He said \"stop.\" Then"), vec![0, 16]);
+        // no whitespace after the terminator
+        assert_eq!(starts("3.5 and e.g.x"), vec![0]);
+        assert_eq!(starts("Wait... What?! Ok"), vec![0, 8, 15]);
+    }
+
+    fn paragraphs() -> Vec<String> {
+        // the second paragraph starts at offset 31
+        vec!["One. Two \"quoted.\" Three? Four".to_string(), "Second par. Next.".to_string()]
+    }
+
+    fn context(start: (usize, usize), end: (usize, usize), window: ContextWindow) -> (String, String) {
+        let commented = DocxCommented { id: 0, data: String::new(), part: StoryPart::Main,
+            start: TextPosition { paragraph: start.0, offset: start.1 },
+            end: TextPosition { paragraph: end.0, offset: end.1 }, point: false };
+        let context = comment_context(&paragraphs(), &commented, &window);
+        (context.before().to_string(), context.after().to_string())
+    }
+
+    fn pair(before: &str, after: &str) -> (String, String) {
+        (before.to_string(), after.to_string())
+    }
+
+    #[test]
+    fn context_of_range_in_paragraph() {
+        // "Three"
+        assert_eq!(context((0, 19), (0, 24), ContextWindow::Chars(4)), pair("d.\" ", "? Fo"));
+        assert_eq!(context((0, 19), (0, 24), ContextWindow::Sentences(0)), pair("", ""));
+        assert_eq!(context((0, 19), (0, 24), ContextWindow::Sentences(1)), pair("", "?"));
+        assert_eq!(context((0, 19), (0, 24), ContextWindow::Sentences(2)), pair("Two \"quoted.\" ", "? Four"));
+    }
+
+    #[test]
+    fn context_is_clamped_to_paragraphs() {
+        // "Four" and the paragraph break, as a range that ends between paragraphs
+        assert_eq!(context((0, 26), (0, 31), ContextWindow::Chars(5)), pair("ree? ", ""));
+        assert_eq!(context((0, 26), (0, 31), ContextWindow::Sentences(1)), pair("", ""));
+        // "Second par.", as a range that begins between paragraphs
+        assert_eq!(context((1, 31), (1, 42), ContextWindow::Chars(3)), pair("", " Ne"));
+        // "Four" up to "Second par.", context from the first and the last paragraph
+        assert_eq!(context((0, 26), (1, 42), ContextWindow::Chars(3)), pair("e? ", " Ne"));
+        assert_eq!(context((0, 26), (1, 42), ContextWindow::Sentences(2)), pair("Three? ", " Next."));
+    }
+
+    /// every range within a paragraph with the context of `window` is the whole paragraph
+    fn assert_whole_paragraphs(window: ContextWindow) {
+        let mut docx = Docx::open("samples/sample-with-comment.docx").unwrap();
+        let mut text = String::new();
+        docx.read_to_string(&mut text).unwrap();
+        let paragraphs: Vec<&str> = text.lines().collect();
+        let in_context = docx.commented_in_context(window).unwrap();
+        let in_paragraph: Vec<&(DocxCommented, CommentContext)> = in_context.iter()
+            .filter(|&(c, _)| *c.part() == StoryPart::Main && !c.is_point() && c.start().paragraph == c.end().paragraph)
+            .collect();
+        assert!(!in_paragraph.is_empty());
+        for (commented, context) in in_paragraph {
+            let whole = format!("{}{}{}", context.before(), commented.text(), context.after());
+            // sentences leave out the whitespace at the end of the paragraph
+            assert_eq!(whole.trim_end(), paragraphs[commented.start().paragraph].trim_end());
+        }
+    }
+
+    #[test]
+    fn large_windows_give_whole_paragraphs() {
+        assert_whole_paragraphs(ContextWindow::Chars(usize::MAX));
+        assert_whole_paragraphs(ContextWindow::Sentences(usize::MAX));
+    }
+
+    const UNCLOSED: &str = r#"<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:body>
+        <w:p><w:commentRangeStart w:id="1"/><w:r><w:t xml:space="preserve">open </w:t></w:r><w:r><w:t>para</w:t></w:r></w:p>
+        <w:p><w:r><w:t>next</w:t></w:r><w:commentRangeEnd w:id="2"/><w:r><w:t>end</w:t></w:r></w:p>
+    </w:body></w:document>"#;
+
+    fn recover(recovery: RangeRecovery) -> (Vec<DocxCommented>, Vec<CommentDiagnostic>) {
+        let part = StoryPart::Main;
+        let mut extractor = CommentedExtractor::new(&part, recovery);
+        let walk = walk_part(UNCLOSED, "word/document.xml", &mut [&mut extractor]).unwrap();
+        extractor.finish(&walk)
+    }
+
+    fn ranges(commented: &[DocxCommented]) -> Vec<(&str, TextPosition)> {
+        commented.iter().map(|c| (c.text(), c.end())).collect()
+    }
+
+    #[test]
+    fn unclosed_ranges_are_reported() {
+        let (commented, diagnostics) = recover(RangeRecovery::Drop);
+        assert!(commented.is_empty());
+        assert_eq!(diagnostics, vec![
+            CommentDiagnostic::EndWithoutStart { id: 2, part: StoryPart::Main, position: TextPosition { paragraph: 1, offset: 14 } },
+            CommentDiagnostic::StartWithoutEnd { id: 1, part: StoryPart::Main, start: TextPosition { paragraph: 0, offset: 0 } }
+        ]);
+    }
+
+    #[test]
+    fn unclosed_ranges_are_recovered() {
+        let (commented, diagnostics) = recover(RangeRecovery::CloseAtParagraphEnd);
+        assert_eq!(ranges(&commented), vec![("open para", TextPosition { paragraph: 0, offset: 9 })]);
+        assert_eq!(diagnostics.len(), 2);
+        let (commented, _) = recover(RangeRecovery::CloseAtEnd);
+        assert_eq!(ranges(&commented), vec![("open para\nnextend\n", TextPosition { paragraph: 1, offset: 18 })]);
+    }
+
+    #[test]
+    fn references_without_range_are_point_comments() {
+        let xml = r#"<w:hdr xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+            <w:p><w:r><w:t>first</w:t></w:r></w:p>
+            <w:p><w:r><w:t>point</w:t></w:r><w:r><w:commentReference w:id="5"/></w:r><w:r><w:t xml:space="preserve"> here</w:t></w:r></w:p>
+        </w:hdr>"#;
+        let part = StoryPart::Header { name: "word/header1.xml".to_string(), kind: None };
+        let mut extractor = CommentedExtractor::new(&part, RangeRecovery::Drop);
+        let walk = walk_part(xml, "word/header1.xml", &mut [&mut extractor]).unwrap();
+        let (commented, diagnostics) = extractor.finish(&walk);
+        assert!(diagnostics.is_empty());
+        assert_eq!(commented.len(), 1);
+        let point = &commented[0];
+        assert!(point.is_point());
+        assert_eq!(point.id(), 5);
+        assert_eq!(*point.part(), part);
+        assert_eq!(point.start(), TextPosition { paragraph: 1, offset: 11 });
+        assert_eq!(point.end(), point.start());
+        // the text of the paragraph around the point
+        assert_eq!(point.text(), "point here");
+    }
+
+    #[test]
+    fn positions_match_plain_text() {
+        let mut docx = Docx::open("samples/sample-with-comment.docx").unwrap();
+        let commented = docx.commented().unwrap();
+        let mut text = String::new();
+        docx.read_to_string(&mut text).unwrap();
+        let chars: Vec<char> = text.chars().collect();
+        let ranges: Vec<&DocxCommented> = commented.iter().filter(|c| !c.is_point()).collect();
+        assert!(ranges.iter().any(|c| c.start().paragraph < c.end().paragraph));
+        for c in ranges {
+            let range: String = chars[c.start().offset..c.end().offset].iter().collect();
+            assert_eq!(range, c.text());
+        }
+    }
+}
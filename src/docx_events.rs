@@ -0,0 +1,447 @@
+//! a pull parser over the content of a story part, e.g. word/document.xml
+//! events are read from the zip entry as they are requested,
+//! so memory stays bounded by the largest element, not by the size of the part
+
+use xml::reader::Reader;
+use xml::events::{Event, BytesStart};
+use xml::errors::Error as XmlError;
+
+use std::collections::VecDeque;
+use std::io::prelude::*;
+use std::io::BufReader;
+use zip::read::ZipFile;
+
+use ::Docx;
+use get_attr::GetAttr;
+use xml_ns::{Ns, NsReader, ResolveNs};
+use error::{Error, Result};
+
+/// the content of a story part, in document order
+/// text is that of `w:t`, as in the plain text of a document
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DocxEvent {
+    /// `w:p`, including the paragraphs of tables; comes after its properties were read
+    ParagraphStart(ParagraphProperties),
+    ParagraphEnd,
+    /// `w:r`, comes after its properties were read
+    RunStart(RunProperties),
+    RunEnd,
+    /// text of the current run
+    Text(String),
+    /// `w:commentRangeStart`
+    CommentRangeStart(usize),
+    /// `w:commentRangeEnd`
+    CommentRangeEnd(usize),
+    /// `w:commentReference`, the comment mark
+    CommentReference(usize),
+    /// an element asked for with `DocxEvents::report`
+    ElementStart(Element),
+    /// the end of an element asked for, by namespace and local name
+    ElementEnd(Ns, String)
+}
+
+/// the direct formatting of a paragraph (`w:pPr`) that the events report
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ParagraphProperties {
+    /// `w:pStyle`, the id of the paragraph style
+    pub style: Option<String>,
+    /// `w:numPr`, the numbering id and indentation level of a list item
+    /// numbering id 0 removes the numbering of the paragraph style
+    pub numbering: Option<(usize, usize)>
+}
+
+/// the direct formatting of a run (`w:rPr`) that the events report
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RunProperties {
+    /// `w:rStyle`, the id of the character style
+    pub style: Option<String>,
+    /// `w:highlight`, e.g. "yellow"
+    pub highlight: Option<String>,
+    /// the fill color of `w:shd`, e.g. "FFFF00" or "auto"; None without `w:shd`
+    pub shading: Option<String>
+}
+
+/// an element that the events do not cover otherwise, e.g. `w:bookmarkStart`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Element {
+    pub ns: Ns,
+    /// the local name, e.g. "bookmarkStart"
+    pub name: String,
+    /// namespace, local name and value of each attribute
+    pub attributes: Vec<(Ns, String, String)>
+}
+
+impl Element {
+    /// the value of an attribute, e.g. `attr(Ns::W, "name")`
+    pub fn attr(&self, ns: Ns, name: &str) -> Option<&str> {
+        self.attributes.iter()
+            .find(|&&(a_ns, ref a_name, _)| a_ns == ns && a_name == name)
+            .map(|(_, _, value)| value.as_str())
+    }
+}
+
+/// a paragraph or run whose start is not reported before its properties are read
+enum Pending {
+    Paragraph(usize, ParagraphProperties), // depth of the element
+    Run(usize, RunProperties)
+}
+
+/// an iterator over the events of a story part
+/// iteration stops after the first error
+pub struct DocxEvents<B: BufRead> {
+    xml_reader: NsReader<B>,
+    part: String,
+    buf: Vec<u8>,
+    depth: usize, // elements open
+    reported: Vec<(Ns, Vec<u8>)>, // elements asked for
+    pending: Option<Pending>,
+    props_depth: Option<usize>, // depth of the w:pPr or w:rPr of the pending element
+    num_props: Option<(Option<usize>, Option<usize>)>, // w:numId and w:ilvl while inside w:numPr
+    deferred: Vec<DocxEvent>, // elements asked for inside the properties of the pending element
+    in_text: bool,
+    queue: VecDeque<DocxEvent>,
+    done: bool
+}
+
+impl<B: BufRead> DocxEvents<B> {
+    /// the events of the xml of a part, `part` is its name for errors
+    pub fn new(xml_reader: Reader<B>, part: &str) -> DocxEvents<B> {
+        DocxEvents {
+            xml_reader: NsReader::new(xml_reader),
+            part: part.to_string(),
+            buf: Vec::new(),
+            depth: 0,
+            reported: Vec::new(),
+            pending: None,
+            props_depth: None,
+            num_props: None,
+            deferred: Vec::new(),
+            in_text: false,
+            queue: VecDeque::new(),
+            done: false
+        }
+    }
+
+    /// also report the start and end of an element, e.g. `report(Ns::W, "bookmarkStart")`
+    /// paragraphs, runs, text and comment markers are always reported
+    pub fn report(&mut self, ns: Ns, local_name: &str) {
+        self.reported.push((ns, local_name.as_bytes().to_vec()));
+    }
+
+    fn is_reported(&self, ns: Ns, local_name: &[u8]) -> bool {
+        self.reported.iter().any(|&(r_ns, ref r_name)| r_ns == ns && r_name.as_slice() == local_name)
+    }
+
+    /// report the start of the pending paragraph or run
+    fn flush_pending(&mut self) {
+        match self.pending.take() {
+            Some(Pending::Paragraph(_, props)) => self.queue.push_back(DocxEvent::ParagraphStart(props)),
+            Some(Pending::Run(_, props)) => self.queue.push_back(DocxEvent::RunStart(props)),
+            None => return
+        }
+        self.props_depth = None;
+        self.num_props = None;
+        self.queue.extend(self.deferred.drain(..));
+    }
+
+    fn push(&mut self, event: DocxEvent) {
+        if self.pending.is_some() {
+            self.deferred.push(event);
+        } else {
+            self.queue.push_back(event);
+        }
+    }
+
+    /// whether an element at the current depth is a direct child of the pending element
+    fn in_pending(&self) -> bool {
+        match self.pending {
+            Some(Pending::Paragraph(depth, _)) | Some(Pending::Run(depth, _)) => depth + 1 == self.depth,
+            None => false
+        }
+    }
+
+    /// read one xml event, and queue what there is to report
+    fn read_event(&mut self) -> Result<()> {
+        let mut buf = ::std::mem::take(&mut self.buf);
+        buf.clear();
+        let res = match self.xml_reader.read_event(&mut buf) {
+            Ok((ns, event)) => self.handle(ns, event),
+            Err(e) => Err(Error::xml(self.xml_reader.buffer_position(), e))
+        };
+        self.buf = buf;
+        res
+    }
+
+    fn handle(&mut self, ns: Ns, event: Event) -> Result<()> {
+        match event {
+            Event::Start(ref e) => {
+                self.depth += 1;
+                let name = e.local_name();
+                // the properties come first, anything else means they are complete
+                let props = ns == Ns::W && (name == b"pPr" || name == b"rPr") && self.in_pending();
+                if self.props_depth.is_none() && !props {
+                    self.flush_pending();
+                }
+                match (ns, name) {
+                    (Ns::W, b"p") => self.pending = Some(Pending::Paragraph(self.depth, ParagraphProperties::default()))
+                    , (Ns::W, b"r") => self.pending = Some(Pending::Run(self.depth, RunProperties::default()))
+                    , _ if props => self.props_depth = Some(self.depth)
+                    , (Ns::W, b"numPr") if self.props_depth == Some(self.depth - 1) => self.num_props = Some((None, None))
+                    , (Ns::W, b"t") => self.in_text = true
+                    , _ => self.element(ns, e)?
+                }
+            }
+            , Event::Empty(ref e) => {
+                let name = e.local_name();
+                if self.props_depth.is_none() {
+                    self.flush_pending();
+                }
+                match (ns, name) {
+                    (Ns::W, b"p") => {
+                        self.queue.push_back(DocxEvent::ParagraphStart(ParagraphProperties::default()));
+                        self.queue.push_back(DocxEvent::ParagraphEnd);
+                    }
+                    , (Ns::W, b"r") => {
+                        self.queue.push_back(DocxEvent::RunStart(RunProperties::default()));
+                        self.queue.push_back(DocxEvent::RunEnd);
+                    }
+                    // direct children of the properties only, not those of w:pPrChange or w:rPrChange
+                    , (Ns::W, b"pStyle") if self.props_depth == Some(self.depth) => {
+                        let style = e.get_attr(&self.xml_reader, Ns::W, b"val")?;
+                        if let Some(Pending::Paragraph(_, ref mut props)) = self.pending {
+                            props.style = Some(style);
+                        }
+                    }
+                    , (Ns::W, b"rStyle") if self.props_depth == Some(self.depth) => {
+                        let style = e.get_attr(&self.xml_reader, Ns::W, b"val")?;
+                        if let Some(Pending::Run(_, ref mut props)) = self.pending {
+                            props.style = Some(style);
+                        }
+                    }
+                    , (Ns::W, b"highlight") if self.props_depth == Some(self.depth) => {
+                        let highlight = e.get_attr(&self.xml_reader, Ns::W, b"val")?;
+                        if let Some(Pending::Run(_, ref mut props)) = self.pending {
+                            props.highlight = Some(highlight);
+                        }
+                    }
+                    , (Ns::W, b"shd") if self.props_depth == Some(self.depth) => {
+                        let fill = shading_fill(&self.xml_reader, e)?;
+                        if let Some(Pending::Run(_, ref mut props)) = self.pending {
+                            props.shading = fill;
+                        }
+                    }
+                    , (Ns::W, b"numId") | (Ns::W, b"ilvl") if self.num_props.is_some() => {
+                        let val = e.get_attr_as::<usize, _>(&self.xml_reader, Ns::W, b"val")?;
+                        self.num_props = self.num_props.map(|(num_id, level)|
+                            if name == b"numId" { (Some(val), level) } else { (num_id, Some(val)) });
+                    }
+                    , _ => {
+                        self.element(ns, e)?;
+                        if self.is_reported(ns, name) {
+                            self.push(DocxEvent::ElementEnd(ns, String::from_utf8_lossy(name).into_owned()));
+                        }
+                    }
+                }
+            }
+            , Event::End(ref e) => {
+                let name = e.local_name();
+                if self.props_depth == Some(self.depth) {
+                    self.props_depth = None;
+                } else if self.props_depth.is_none() {
+                    self.flush_pending();
+                }
+                self.depth = self.depth.saturating_sub(1);
+                match (ns, name) {
+                    (Ns::W, b"p") => self.queue.push_back(DocxEvent::ParagraphEnd)
+                    , (Ns::W, b"r") => self.queue.push_back(DocxEvent::RunEnd)
+                    , (Ns::W, b"numPr") if self.num_props.is_some() => {
+                        if let Some((Some(num_id), level)) = self.num_props.take() {
+                            if let Some(Pending::Paragraph(_, ref mut props)) = self.pending {
+                                props.numbering = Some((num_id, level.unwrap_or(0)));
+                            }
+                        }
+                    }
+                    , (Ns::W, b"t") => self.in_text = false
+                    , _ if self.is_reported(ns, name) => {
+                        self.push(DocxEvent::ElementEnd(ns, String::from_utf8_lossy(name).into_owned()));
+                    }
+                    , _ => ()
+                }
+            }
+            , Event::Text(ref e) if self.in_text => {
+                let text = self.xml_reader.text(e)?;
+                self.queue.push_back(DocxEvent::Text(text));
+            }
+            , Event::Eof => {
+                self.flush_pending();
+                self.done = true;
+            }
+            , _ => ()
+        }
+        Ok(())
+    }
+
+    /// the comment markers, usually empty elements, and the elements asked for
+    fn element(&mut self, ns: Ns, e: &BytesStart) -> Result<()> {
+        let marker: Option<fn(usize) -> DocxEvent> = match (ns, e.local_name()) {
+            (Ns::W, b"commentRangeStart") => Some(DocxEvent::CommentRangeStart)
+            , (Ns::W, b"commentRangeEnd") => Some(DocxEvent::CommentRangeEnd)
+            , (Ns::W, b"commentReference") => Some(DocxEvent::CommentReference)
+            , _ => None
+        };
+        if let Some(marker) = marker {
+            let a_id = e.get_attr_as::<usize, _>(&self.xml_reader, Ns::W, b"id")?;
+            self.push(marker(a_id));
+        } else if self.is_reported(ns, e.local_name()) {
+            let element = read_element(&self.xml_reader, ns, e)?;
+            self.push(DocxEvent::ElementStart(element));
+        }
+        Ok(())
+    }
+}
+
+/// the fill color of a `w:shd`, "auto" for the pattern "nil" that removes shading
+pub(crate) fn shading_fill<R: ResolveNs>(names: &R, e: &BytesStart) -> Result<Option<String>> {
+    if e.get_attr_opt(names, Ns::W, b"val")?.as_deref() == Some("nil") {
+        return Ok(Some("auto".to_string()));
+    }
+    e.get_attr_opt(names, Ns::W, b"fill")
+}
+
+fn read_element<R: ResolveNs>(names: &R, ns: Ns, e: &BytesStart) -> Result<Element> {
+    let malformed = |err: XmlError| Error::xml(names.buffer_position(), format!("attributes of '{}': {}", String::from_utf8_lossy(e.name()), err));
+    let mut attributes = Vec::new();
+    for attr in e.attributes() {
+        let attr = attr.map_err(&malformed)?;
+        let value = attr.unescaped_value().map_err(&malformed)?;
+        let (a_ns, a_name) = names.resolve_attr(attr.key);
+        attributes.push((a_ns, String::from_utf8_lossy(a_name).into_owned(), String::from_utf8_lossy(&value).into_owned()));
+    }
+    Ok(Element { ns, name: String::from_utf8_lossy(e.local_name()).into_owned(), attributes })
+}
+
+impl<B: BufRead> Iterator for DocxEvents<B> {
+    type Item = Result<DocxEvent>;
+
+    fn next(&mut self) -> Option<Result<DocxEvent>> {
+        loop {
+            if let Some(event) = self.queue.pop_front() {
+                return Some(Ok(event));
+            }
+            if self.done {
+                return None;
+            }
+            if let Err(e) = self.read_event() {
+                self.done = true;
+                self.queue.clear();
+                return Some(Err(e.in_part(&self.part)));
+            }
+        }
+    }
+}
+
+impl Docx {
+    /// stream the events of the main document from the archive,
+    /// without reading the part into memory
+    /// the document is borrowed mutably while the events are read
+    pub fn events<'a>(&'a mut self) -> Result<DocxEvents<BufReader<ZipFile<'a>>>> {
+        let name = self.main_part_name().to_string();
+        let entry = self.entry(&name)?;
+        Ok(DocxEvents::new(Reader::from_reader(BufReader::new(entry)), &name))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use doc::MsDoc;
+
+    const W: &str = r#"xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main""#;
+
+    fn events(body: &str, reported: &[&str]) -> Vec<DocxEvent> {
+        let xml = format!("<w:body {}>{}</w:body>", W, body);
+        let mut events = DocxEvents::new(Reader::from_str(&xml), "word/document.xml");
+        for name in reported {
+            events.report(Ns::W, name);
+        }
+        events.collect::<Result<Vec<DocxEvent>>>().unwrap()
+    }
+
+    fn paragraph(style: Option<&str>, numbering: Option<(usize, usize)>) -> DocxEvent {
+        DocxEvent::ParagraphStart(ParagraphProperties { style: style.map(String::from), numbering })
+    }
+
+    fn run(style: Option<&str>, highlight: Option<&str>, shading: Option<&str>) -> DocxEvent {
+        DocxEvent::RunStart(RunProperties {
+            style: style.map(String::from), highlight: highlight.map(String::from), shading: shading.map(String::from)
+        })
+    }
+
+    fn text(text: &str) -> DocxEvent {
+        DocxEvent::Text(text.to_string())
+    }
+
+    #[test]
+    fn properties_come_with_the_start() {
+        let events = events(r#"<w:p>
+            <w:pPr><w:pStyle w:val="ListParagraph"/><w:numPr><w:ilvl w:val="1"/><w:numId w:val="3"/></w:numPr></w:pPr>
+            <w:r><w:rPr><w:rStyle w:val="Strong"/><w:highlight w:val="yellow"/><w:shd w:val="clear" w:fill="FF0000"/></w:rPr><w:t>item</w:t></w:r>
+        </w:p>"#, &[]);
+        assert_eq!(events, vec![
+            paragraph(Some("ListParagraph"), Some((3, 1))),
+            run(Some("Strong"), Some("yellow"), Some("FF0000")), text("item"), DocxEvent::RunEnd,
+            DocxEvent::ParagraphEnd
+        ]);
+    }
+
+    #[test]
+    fn marks_and_changes_keep_their_properties() {
+        // the w:rPr of the paragraph mark, and the formatting before a tracked change
+        let events = events(r#"<w:p>
+            <w:pPr><w:rPr><w:highlight w:val="red"/></w:rPr><w:pPrChange w:id="1"><w:pPr><w:pStyle w:val="Old"/></w:pPr></w:pPrChange></w:pPr>
+            <w:r><w:rPr><w:rPrChange w:id="2"><w:rPr><w:highlight w:val="green"/></w:rPr></w:rPrChange></w:rPr><w:t>x</w:t></w:r>
+        </w:p>"#, &[]);
+        assert_eq!(events, vec![
+            paragraph(None, None), run(None, None, None), text("x"), DocxEvent::RunEnd, DocxEvent::ParagraphEnd
+        ]);
+    }
+
+    #[test]
+    fn elements_in_properties_come_after_the_start() {
+        let events = events(r#"<w:p><w:pPr><w:bookmarkStart w:id="0" w:name="top"/><w:jc w:val="center"/></w:pPr><w:r><w:t>x</w:t></w:r></w:p>"#,
+            &["bookmarkStart"]);
+        let bookmark = Element { ns: Ns::W, name: "bookmarkStart".to_string(), attributes: vec![
+            (Ns::W, "id".to_string(), "0".to_string()), (Ns::W, "name".to_string(), "top".to_string())
+        ] };
+        assert_eq!(events, vec![
+            paragraph(None, None),
+            DocxEvent::ElementStart(bookmark), DocxEvent::ElementEnd(Ns::W, "bookmarkStart".to_string()),
+            run(None, None, None), text("x"), DocxEvent::RunEnd, DocxEvent::ParagraphEnd
+        ]);
+    }
+
+    #[test]
+    fn empty_paragraphs_and_runs() {
+        let events = events(r#"<w:p/><w:p><w:r/></w:p>"#, &[]);
+        assert_eq!(events, vec![
+            paragraph(None, None), DocxEvent::ParagraphEnd,
+            paragraph(None, None), run(None, None, None), DocxEvent::RunEnd, DocxEvent::ParagraphEnd
+        ]);
+    }
+
+    #[test]
+    fn streamed_text_is_the_plain_text() {
+        let mut docx = Docx::open("samples/sample-with-comment.docx").unwrap();
+        let mut text = String::new();
+        docx.read_to_string(&mut text).unwrap();
+        let mut streamed = String::new();
+        for event in docx.events().unwrap() {
+            match event.unwrap() {
+                DocxEvent::Text(t) => streamed.push_str(&t),
+                DocxEvent::ParagraphEnd => streamed.push('\n'),
+                _ => ()
+            }
+        }
+        assert_eq!(streamed, text);
+    }
+}
@@ -1,206 +1,411 @@
-use zip::ZipArchive;
+use std::path::Path;
+use std::clone::Clone;
+use std::fmt;
 
-use xml::reader::Reader;
-use xml::events::{Event,BytesStart};
-use std::io::{Error, ErrorKind};
+use ::Docx;
+use doc::MsDoc;
+use docx::{TextPosition, StoryPart};
+use docx_events::RunProperties;
+use docx_styles::Styles;
+use docx_walk::{Walk, Visitor};
+use docx_ranges::{RangeId, AnnotatedRange, RangeKind};
+use error::Result;
 
-use std::path::{Path, PathBuf};
-use std::fs::File;
-use std::io::prelude::*;
-use std::io;
-use std::clone::Clone;
-use zip::read::ZipFile;
 
-use std::collections::HashMap;
+/// a highlight color of a run (`w:highlight`), the values of ST_HighlightColor
+/// but "none", which is no highlight;
+/// the same in every document, so highlights of several documents can be compared
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum HighlightColor {
+    Black,
+    Blue,
+    Cyan,
+    Green,
+    Magenta,
+    Red,
+    Yellow,
+    White,
+    DarkBlue,
+    DarkCyan,
+    DarkGreen,
+    DarkMagenta,
+    DarkRed,
+    DarkYellow,
+    DarkGray,
+    LightGray
+}
 
-use ::Docx;
-use get_attr::GetAttr;
+impl HighlightColor {
+    /// all colors, in the order of the schema
+    pub const ALL: [HighlightColor; 16] = [
+        HighlightColor::Black, HighlightColor::Blue, HighlightColor::Cyan, HighlightColor::Green,
+        HighlightColor::Magenta, HighlightColor::Red, HighlightColor::Yellow, HighlightColor::White,
+        HighlightColor::DarkBlue, HighlightColor::DarkCyan, HighlightColor::DarkGreen, HighlightColor::DarkMagenta,
+        HighlightColor::DarkRed, HighlightColor::DarkYellow, HighlightColor::DarkGray, HighlightColor::LightGray
+    ];
 
+    /// the color of a `w:val`, e.g. "darkBlue";
+    /// None for "none" and for values not in the schema
+    pub fn from_val(val: &str) -> Option<HighlightColor> {
+        HighlightColor::ALL.iter().cloned().find(|c| c.name() == val)
+    }
 
+    /// the name used in `w:val`, e.g. "darkBlue"
+    pub fn name(&self) -> &'static str {
+        match *self {
+            HighlightColor::Black => "black"
+            , HighlightColor::Blue => "blue"
+            , HighlightColor::Cyan => "cyan"
+            , HighlightColor::Green => "green"
+            , HighlightColor::Magenta => "magenta"
+            , HighlightColor::Red => "red"
+            , HighlightColor::Yellow => "yellow"
+            , HighlightColor::White => "white"
+            , HighlightColor::DarkBlue => "darkBlue"
+            , HighlightColor::DarkCyan => "darkCyan"
+            , HighlightColor::DarkGreen => "darkGreen"
+            , HighlightColor::DarkMagenta => "darkMagenta"
+            , HighlightColor::DarkRed => "darkRed"
+            , HighlightColor::DarkYellow => "darkYellow"
+            , HighlightColor::DarkGray => "darkGray"
+            , HighlightColor::LightGray => "lightGray"
+        }
+    }
 
-// TODO: model as extension of a "identified range"
-pub struct DocxHighlight {
-  pub id: usize,  // hightlight color according to internal stringtable
-  data: String
+    /// the color closest to `rgb`, by distance in RGB space
+    pub fn nearest(rgb: (u8, u8, u8)) -> HighlightColor {
+        let distance = |c: &HighlightColor| {
+            let (r, g, b) = c.rgb();
+            let d = |x: u8, y: u8| (i32::from(x) - i32::from(y)).pow(2);
+            d(r, rgb.0) + d(g, rgb.1) + d(b, rgb.2)
+        };
+        *HighlightColor::ALL.iter().min_by_key(|c| distance(c)).unwrap()
+    }
+
+    /// red, green and blue, as given in the schema, e.g. (0xFF, 0xFF, 0x00) for yellow
+    pub fn rgb(&self) -> (u8, u8, u8) {
+        match *self {
+            HighlightColor::Black => (0x00, 0x00, 0x00)
+            , HighlightColor::Blue => (0x00, 0x00, 0xFF)
+            , HighlightColor::Cyan => (0x00, 0xFF, 0xFF)
+            , HighlightColor::Green => (0x00, 0xFF, 0x00)
+            , HighlightColor::Magenta => (0xFF, 0x00, 0xFF)
+            , HighlightColor::Red => (0xFF, 0x00, 0x00)
+            , HighlightColor::Yellow => (0xFF, 0xFF, 0x00)
+            , HighlightColor::White => (0xFF, 0xFF, 0xFF)
+            , HighlightColor::DarkBlue => (0x00, 0x00, 0x80)
+            , HighlightColor::DarkCyan => (0x00, 0x80, 0x80)
+            , HighlightColor::DarkGreen => (0x00, 0x80, 0x00)
+            , HighlightColor::DarkMagenta => (0x80, 0x00, 0x80)
+            , HighlightColor::DarkRed => (0x80, 0x00, 0x00)
+            , HighlightColor::DarkYellow => (0x80, 0x80, 0x00)
+            , HighlightColor::DarkGray => (0x80, 0x80, 0x80)
+            , HighlightColor::LightGray => (0xC0, 0xC0, 0xC0)
+        }
+    }
+}
+
+impl fmt::Display for HighlightColor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
 }
 
-pub trait RangeText {
-  fn text(&self) -> &str;
+/// an RGB color of the form "RRGGBB", e.g. the fill of `w:shd`;
+/// None for "auto" and other values
+pub fn parse_rgb(val: &str) -> Option<(u8, u8, u8)> {
+    if val.len() != 6 || !val.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    let channel = |i: usize| u8::from_str_radix(&val[i..i + 2], 16).ok();
+    Some((channel(0)?, channel(2)?, channel(4)?))
 }
 
-pub trait RangeId {
-    fn id(&self) -> usize;
+/// whether run shading (`w:shd`) counts as highlighting
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Shading {
+    /// only `w:highlight`
+    Ignore,
+    /// shaded text is highlighted with the color nearest to its fill;
+    /// `w:highlight` takes precedence in a run with both
+    AsHighlight
 }
 
-impl RangeText for DocxHighlight {
-  fn text(&self) -> &str {
-    self.data.as_str()
-  }
+/// a range of text with one highlight color
+/// ranges end at paragraph breaks, so that start and end lie in the same paragraph;
+/// a highlight that goes on in the next paragraph is continued by the next range
+pub struct DocxHighlight {
+  color: HighlightColor,
+  rgb: (u8, u8, u8),
+  data: String,
+  part: StoryPart,
+  start: TextPosition,
+  end: TextPosition,
+  continues: bool
 }
 
+impl DocxHighlight {
+    /// the highlight color, or for shading the one nearest to its fill
+    pub fn color(&self) -> HighlightColor {
+        self.color
+    }
+
+    /// the exact color, the fill for shading
+    pub fn rgb(&self) -> (u8, u8, u8) {
+        self.rgb
+    }
+
+    /// the paragraph the range lies in, counted from 0
+    pub fn paragraph(&self) -> usize {
+        self.start.paragraph
+    }
+
+    /// true if the range continues the previous one, split at a paragraph break
+    pub fn continues(&self) -> bool {
+        self.continues
+    }
+}
+
+/// the id is the position of the color in `HighlightColor::ALL`
 impl RangeId for DocxHighlight {
     fn id(&self) -> usize {
-        self.id
+        self.color as usize
     }
 }
 
-pub trait ReadHighlights<T> {
-  ///  extract all highlighted ranges from document
-  fn open_highlighted<P: AsRef<Path>>(path: P) -> io::Result<(HashMap<usize,String>,Vec<DocxHighlight>)>;
-}
+/// the label is the color name, e.g. "yellow"
+impl AnnotatedRange for DocxHighlight {
+    fn kind(&self) -> RangeKind {
+        RangeKind::Highlight
+    }
 
-impl ReadHighlights<Docx> for Docx {
+    fn label(&self) -> String {
+        self.color.to_string()
+    }
 
-  // take note that .docx only supports 16 colors
-  fn open_highlighted<P: AsRef<Path>>(path: P) ->  io::Result<(HashMap<usize,String>,Vec<DocxHighlight>)> {
-    let file = File::open(path.as_ref())?;
-    let mut archive = ZipArchive::new(file)?;
+    fn text(&self) -> &str {
+        self.data.as_str()
+    }
 
-    let mut xml_data = String::new();
+    fn part(&self) -> &StoryPart {
+        &self.part
+    }
 
-    for i in 0..archive.len() {
-        let mut c_file = archive.by_index(i).unwrap();
-        if c_file.name() == "word/document.xml" {
-            c_file.read_to_string(&mut xml_data);
-            break
-        }
+    fn start(&self) -> TextPosition {
+        self.start
     }
-    // post: if no document.xml was found, then no data was read to buffer
 
-    if xml_data.len() == 0 {
-      return Err(io::Error::new(io::ErrorKind::NotFound,".docx invalid: did not contain word/document.xml".to_string()))
+    fn end(&self) -> TextPosition {
+        self.end
     }
+}
+
+pub trait ReadHighlights<T: MsDoc<T> + ReadHighlights<T>> {
+  ///  extract all highlighted ranges from document
+  /// contiguous runs of the same color form one range, in order of appearance
+  /// a run has the highlight of its direct formatting, or the one it inherits from its styles
+  fn highlighted(&self) -> Result<Vec<DocxHighlight>>;
+  /// extract the highlighted ranges, shaded text included with `Shading::AsHighlight`
+  fn highlighted_with(&self, shading: Shading) -> Result<Vec<DocxHighlight>>;
 
-    let xml_reader = Reader::from_str(xml_data.as_ref());
-    read_highlighted(xml_reader)
+  /// open the file and extract all highlighted ranges
+  fn open_highlighted<P: AsRef<Path>>(path: P) -> Result<Vec<DocxHighlight>> {
+    T::open(path)?.highlighted()
+  }
+
+  /// open the file and extract the highlighted ranges, see `highlighted_with`
+  fn open_highlighted_with<P: AsRef<Path>>(path: P, shading: Shading) -> Result<Vec<DocxHighlight>> {
+    T::open(path)?.highlighted_with(shading)
+  }
+}
+
+impl ReadHighlights<Docx> for Docx {
+
+  fn highlighted(&self) -> Result<Vec<DocxHighlight>> {
+    self.highlighted_with(Shading::Ignore)
+  }
+
+  fn highlighted_with(&self, shading: Shading) -> Result<Vec<DocxHighlight>> {
+    let mut extractor = HighlightExtractor::new(shading, self.styles()?);
+    self.visit(&mut [&mut extractor])?;
+    Ok(extractor.finish())
   }
 }
 
-#[derive(PartialEq)]
-enum ToRead {
-  NoRead, NextWt, NextText
+/// collects the text of highlighted runs:
+/// contiguous runs with the same highlight form one range, text without highlight
+/// (or with highlight "none") or with another highlight closes it, as does the end of a paragraph;
+/// runs without text, e.g. comment references, neither close nor extend a range
+pub(crate) struct HighlightExtractor {
+    shading: Shading,
+    styles: Styles, // for the highlight that runs inherit
+    txt: Vec<String>, // a range
+    par: Vec<DocxHighlight>,
+    range_start: Option<TextPosition>,
+    range_end: TextPosition,
+    range_continues: bool,
+    split: bool, // whether the previous range was split at a paragraph break
+    prev_highlight: Option<(HighlightColor, (u8, u8, u8))> // highlight of the last text
 }
 
-fn invert_hashmap<A: Clone,B: std::hash::Hash + Eq + Copy>(map: HashMap<A,B>) -> HashMap<B,A>
-{
-  let mut res = HashMap::with_capacity(map.len());
-  for (key,val) in map.iter() {
-    match res.insert((*val).clone(),(*key).clone()) {
-      None => ()
-    , Some(_) => eprintln!("Warning: invert_hashmap() key collision")
+impl HighlightExtractor {
+    pub fn new(shading: Shading, styles: Styles) -> HighlightExtractor {
+        HighlightExtractor { shading, styles, txt: Vec::new(), par: Vec::new(), range_start: None,
+            range_end: TextPosition::default(), range_continues: false, split: false, prev_highlight: None }
+    }
+
+    pub fn finish(mut self) -> Vec<DocxHighlight> {
+        // write buffer to result after last range
+        self.flush();
+        self.par
+    }
+
+    fn flush(&mut self) {
+        if let (Some((color, rgb)), Some(start)) = (self.prev_highlight, self.range_start.take()) {
+            // range ended, push to result
+            let highlighted_range = DocxHighlight{ color, rgb, data: self.txt.join(""),
+                part: StoryPart::Main, start, end: self.range_end, continues: self.range_continues };
+            self.par.push(highlighted_range);
+            self.txt = Vec::new();
+        }
     }
-  }
-  return res;
 }
 
-fn read_highlighted<B: BufRead>(mut xml_reader: Reader<B>) -> io::Result<(HashMap<usize,String>,Vec<DocxHighlight>)> {
-    let mut buf = Vec::new();
-    let mut txt = Vec::new(); // a range
-    let mut par = Vec::new();
-
-    let mut stringtable: HashMap<String,usize> = HashMap::new(); // map color names to ints
-    let mut highlight_ranges_open: HashMap<usize,String> = HashMap::new();
-    let mut prev_highlight_id: Option<usize> = None;
-    let mut cur_highlight_id : Option<usize> = None;
-
-    let mut n_par = 0; // count paragraphs
-    let mut n_r   = 0; // count ranges (total, spanning over paragraphs)
-
-    let mut to_read = ToRead::NoRead;
-    loop {
-        // TODO: track highlights, in order to combine non-interleaved
-        // ranges of same color
-        match xml_reader.read_event(&mut buf) {
-            Ok(Event::Start(ref e)) => {
-                match e.name() {
-                    b"w:p" => {
-                        n_par += 1;
-                    }
-                    , b"w:r" => { // any range 
-                        n_r += 1;
-                        // set state machine to look for <w:highlight w:val="..."/>
-                        cur_highlight_id = None;
-                        () // TODO
-                    }
-                    , b"w:t" => { // entered a text section
-                        if to_read == ToRead::NextWt {
-                          to_read = ToRead::NextText;
-                        }
-                    }
-                    , b"w:highlight" => {
-                      println!("<w:highlight>");
-                    }
-                    , _ => ()
-                }
-            }
-            , Ok(Event::Empty(ref e)) => {
-                match e.name() {
-                    b"w:highlight" => { // expected within <w:r><w:rPr>
-                        let cur_highlight_val = e.get_attr(b"w:val"); // e.g. yellow, red
-                        // lookup color id or generate anew
-                        let next_id = stringtable.len();
-                        cur_highlight_id = Some(*stringtable.entry(cur_highlight_val).or_insert(next_id));
-
-                        to_read = ToRead::NextWt;                      
-                    }
-                    , _ => ()
-                }
-            }
-            , Ok(Event::End(ref e)) => {
-                match e.name() {
-                    b"w:rPr" => {
-                        // flush if a new highlight color appears
-                        if cur_highlight_id != prev_highlight_id {
-                            match prev_highlight_id {
-                                None => ()
-                              , Some(x) => {
-                                  // range ended, push to result
-                                  let highlighted_range = DocxHighlight{ id: x, data: txt.join("") };
-                                  par.push(highlighted_range);
-                                  txt = Vec::new();
-                                }
-                            }
-                        } // else do nothing
-                    }
-                    , b"w:r" => {
-                        prev_highlight_id = cur_highlight_id;
-                    }
-                    , _ => ()
-                }
-            }
-            , Ok(Event::Text(e)) => {
-                if to_read == ToRead::NextText {
-                    //print!("text:");
-                    //println!("{}", e.unescape_and_decode(&xml_reader).unwrap());
-                    txt.push(e.unescape_and_decode(&xml_reader).unwrap());
-                    to_read = ToRead::NoRead;
-                }
-            }
-            , Ok(Event::Eof) => break
-            , Ok(_) => ()
-            , Err(e) => panic!("Error at position {}: {:?}", xml_reader.buffer_position(), e),
+impl Visitor for HighlightExtractor {
+    fn paragraph_end(&mut self, _walk: &Walk) {
+        // the same color in the next paragraph continues the range
+        if self.range_start.is_some() {
+            self.flush();
+            self.split = true;
         }
     }
 
-    // write buffer to result after last range
-    match prev_highlight_id {
-        None => ()
-      , Some(x) => {
-          // range ended, push to result
-          let highlighted_range = DocxHighlight{ id: x, data: txt.join("") };
-          par.push(highlighted_range);
-          //txt = Vec::new();
+    fn text(&mut self, text: &str, walk: &Walk) {
+        let no_props = RunProperties::default();
+        let props = self.styles.run_properties(walk.paragraph_properties(), walk.run_properties().unwrap_or(&no_props));
+        // "none" and unknown colors are no highlight, likewise a fill of "auto"
+        let cur_highlight = match props.highlight.as_deref().and_then(HighlightColor::from_val) {
+            Some(color) => Some((color, color.rgb()))
+            , None if self.shading == Shading::AsHighlight =>
+                props.shading.as_deref().and_then(parse_rgb).map(|rgb| (HighlightColor::nearest(rgb), rgb))
+            , None => None
+        };
+        // flush if the highlight changes
+        if cur_highlight != self.prev_highlight {
+            self.flush();
+            self.split = false;
+            self.prev_highlight = cur_highlight;
         }
+        if cur_highlight.is_some() {
+            let start = walk.start();
+            if self.range_start.is_none() {
+                self.range_start = Some(start);
+                self.range_continues = self.split;
+                self.split = false;
+            }
+            self.range_end = TextPosition { paragraph: start.paragraph, offset: start.offset + text.chars().count() };
+            self.txt.push(text.to_string());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use docx_ranges::AnnotatedRange;
+    use docx_styles::read_styles;
+    use docx_walk::walk_part;
+    use xml::reader::Reader;
+    use std::io::prelude::*;
+
+    fn sample() -> Docx {
+        Docx::open("samples/sample-with-highlight.docx").unwrap()
     }
 
-    // invert stringtable
-    let inverted_stringtable = invert_hashmap(stringtable);
+    #[test]
+    fn merges_runs_of_same_color() {
+        let highlighted = sample().highlighted().unwrap();
+        let ranges: Vec<(HighlightColor, &str)> = highlighted.iter().map(|h| (h.color(), h.text())).collect();
+        // "accumsan et " and "ius" are separate runs
+        assert_eq!(ranges, vec![
+            (HighlightColor::Yellow, "Sample File"),
+            (HighlightColor::Red, "accumsan et ius"),
+            (HighlightColor::Red, "im qui bla"),
+            (HighlightColor::Red, "gue")
+        ]);
+    }
+
+    const SHADED: &str = r#"<w:body xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:p>
+        <w:r><w:rPr><w:shd w:val="clear" w:fill="FFFF00"/></w:rPr><w:t>shaded</w:t></w:r>
+        <w:r><w:rPr><w:shd w:val="clear" w:fill="auto"/></w:rPr><w:t>auto</w:t></w:r>
+        <w:r><w:rPr><w:shd w:val="nil" w:fill="FFFF00"/></w:rPr><w:t>nil</w:t></w:r>
+        <w:r><w:rPr><w:highlight w:val="green"/><w:shd w:val="clear" w:fill="FFFF00"/></w:rPr><w:t>both</w:t></w:r>
+        <w:r><w:rPr><w:rStyle w:val="Shaded"/></w:rPr><w:t>styled</w:t></w:r>
+    </w:p></w:body>"#;
 
+    const STYLES: &str = r#"<w:styles xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+        <w:style w:type="character" w:styleId="Shaded"><w:rPr><w:shd w:val="clear" w:fill="FF0000"/></w:rPr></w:style>
+    </w:styles>"#;
 
-    // could also panic
-    //if txt.len() > 0 {
-    //    eprintln!("After reading all comments, buffer still contained: {}", txt.join(""));
-    //}
+    fn shaded(shading: Shading) -> Vec<(HighlightColor, String)> {
+        let styles = read_styles(Reader::from_str(STYLES)).unwrap();
+        let mut extractor = HighlightExtractor::new(shading, styles);
+        walk_part(SHADED, "word/document.xml", &mut [&mut extractor]).unwrap();
+        extractor.finish().iter().map(|h| (h.color(), h.text().to_string())).collect()
+    }
+
+    #[test]
+    fn shading_counts_as_highlight_if_asked() {
+        assert_eq!(shaded(Shading::AsHighlight), vec![
+            (HighlightColor::Yellow, "shaded".to_string()),
+            // w:highlight wins over w:shd
+            (HighlightColor::Green, "both".to_string()),
+            // inherited from the character style
+            (HighlightColor::Red, "styled".to_string())
+        ]);
+        assert_eq!(shaded(Shading::Ignore), vec![(HighlightColor::Green, "both".to_string())]);
+    }
 
-    Ok((inverted_stringtable,par))
-}
\ No newline at end of file
+    #[test]
+    fn colors_read_schema_values() {
+        assert_eq!(HighlightColor::from_val("darkBlue"), Some(HighlightColor::DarkBlue));
+        assert_eq!(HighlightColor::from_val("none"), None);
+        assert_eq!(HighlightColor::Yellow.rgb(), (0xFF, 0xFF, 0x00));
+        assert!(HighlightColor::ALL.iter().all(|&c| HighlightColor::from_val(c.name()) == Some(c)));
+        assert!(HighlightColor::ALL.iter().enumerate().all(|(i, &c)| c as usize == i));
+    }
+
+    #[test]
+    fn shading_maps_to_nearest_color() {
+        assert_eq!(parse_rgb("ffff00"), Some((0xFF, 0xFF, 0x00)));
+        assert_eq!(parse_rgb("auto"), None);
+        assert_eq!(HighlightColor::nearest((0xFF, 0xFF, 0x00)), HighlightColor::Yellow);
+        assert_eq!(HighlightColor::nearest((0xF0, 0xE0, 0x20)), HighlightColor::Yellow);
+        assert_eq!(HighlightColor::nearest((0xE0, 0x10, 0x10)), HighlightColor::Red);
+    }
+
+    #[test]
+    fn unhighlighted_text_closes_range() {
+        let highlighted = sample().highlighted().unwrap();
+        // "ius" is followed by the unhighlighted "to" of "iusto", "im qui bla" by "ndit"
+        let red: Vec<&DocxHighlight> = highlighted.iter().filter(|h| h.color() == HighlightColor::Red).collect();
+        assert!(red.windows(2).all(|w| w[0].end() < w[1].start()));
+        assert!(highlighted.iter().all(|h| !h.continues()));
+    }
+
+    #[test]
+    fn positions_match_plain_text() {
+        let mut docx = sample();
+        let highlighted = docx.highlighted().unwrap();
+        let mut text = String::new();
+        docx.read_to_string(&mut text).unwrap();
+        let chars: Vec<char> = text.chars().collect();
+        for h in highlighted.iter() {
+            assert_eq!(h.start().paragraph, h.end().paragraph);
+            let range: String = chars[h.start().offset..h.end().offset].iter().collect();
+            assert_eq!(range, h.text());
+        }
+        let paragraphs: Vec<usize> = highlighted.iter().map(|h| h.paragraph()).collect();
+        assert_eq!(paragraphs, vec![3, 11, 11, 11]);
+    }
+}
@@ -1,23 +1,66 @@
 
 
-use zip::ZipArchive;
-
 use xml::reader::Reader;
-use xml::events::{Event,BytesStart};
-use std::io::{Error, ErrorKind};
+use xml::events::Event;
 
-use std::path::{Path, PathBuf};
-use std::fs::File;
+use std::path::Path;
 use std::io::prelude::*;
-use std::io;
 use std::clone::Clone;
-use zip::read::ZipFile;
 
 use ::Docx;
+use doc::MsDoc;
+use docx_rels::{REL_NUMBERING, part_dir};
+use docx_walk::{Walk, Visitor};
+use get_attr::GetAttr;
+use xml_ns::{Ns, NsReader};
+use error::{Error, Result};
 
 use std::collections::HashMap;
-pub trait ReadNumbering<T> {
-    fn open_numbering<P: AsRef<Path>>(path: P) -> io::Result<HashMap<numId,DocxNumbering>>;
+pub trait ReadNumbering<T: MsDoc<T> + ReadNumbering<T>> {
+    fn numbering(&self) -> Result<HashMap<numId,DocxNumbering>>;
+    /// the paragraphs of the main document that are list items
+    fn numbered_paragraphs(&self) -> Result<Vec<NumberedParagraph>>;
+
+    /// open the file and read the numbering definitions
+    fn open_numbering<P: AsRef<Path>>(path: P) -> Result<HashMap<numId,DocxNumbering>> {
+        T::open(path)?.numbering()
+    }
+
+    /// open the file and find the paragraphs that are list items
+    fn open_numbered_paragraphs<P: AsRef<Path>>(path: P) -> Result<Vec<NumberedParagraph>> {
+        T::open(path)?.numbered_paragraphs()
+    }
+}
+
+/// a paragraph that uses a numbering definition (`w:numPr` in its properties)
+/// numbering inherited from the paragraph style is not included
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NumberedParagraph {
+    /// counted from 0, like `TextPosition::paragraph`
+    pub paragraph: usize,
+    pub num_id: numId,
+    /// the indentation level, from 0
+    pub level: usize
+}
+
+/// collects the paragraphs that are list items
+#[derive(Default)]
+pub(crate) struct NumberingExtractor {
+    res: Vec<NumberedParagraph>
+}
+
+impl NumberingExtractor {
+    pub fn finish(self) -> Vec<NumberedParagraph> {
+        self.res
+    }
+}
+
+impl Visitor for NumberingExtractor {
+    fn paragraph_start(&mut self, walk: &Walk) {
+        if let Some((num_id, level)) = walk.paragraph_properties().and_then(|p| p.numbering) {
+            self.res.push(NumberedParagraph { paragraph: walk.paragraph(), num_id, level });
+        }
+    }
 }
 
 pub struct DocxNumbering {
@@ -47,32 +90,33 @@ impl DocxNumFmt {
 /// numbering.xml maps "numId" to "abstractNumId"
 /// and then "abstractNumId" to formats
 impl ReadNumbering<Docx> for Docx {
-    fn open_numbering<P: AsRef<Path>>(path: P) -> io::Result<HashMap<numId,DocxNumbering>> {
-
-        let file = File::open(path.as_ref())?;
-        let mut archive = ZipArchive::new(file)?;
+    fn numbering(&self) -> Result<HashMap<numId,DocxNumbering>> {
+        let (name, xml_data) = match self.related_part(REL_NUMBERING)? {
+            Some(part) => part,
+            None => return Err(Error::MissingPart(self.numbering_part_name()?))
+        };
+        let xml_reader = Reader::from_str(xml_data.as_ref());
+        read_and_join_numbering(xml_reader).map_err(|e| e.in_part(&name))
+    }
 
-        let mut xml_data = String::new();
+    fn numbered_paragraphs(&self) -> Result<Vec<NumberedParagraph>> {
+        let mut extractor = NumberingExtractor::default();
+        self.visit(&mut [&mut extractor])?;
+        Ok(extractor.finish())
+    }
+}
 
-        for i in 0..archive.len() {
-            let mut c_file = archive.by_index(i).unwrap();
-            if c_file.name() == "word/numbering.xml" {
-                c_file.read_to_string(&mut xml_data);
-                break
-            }
-        }
-        // post: if no document.xml was found, then no data was read to buffer
-        
-        if xml_data.len() == 0 {
-            return Err(io::Error::new(io::ErrorKind::NotFound,".docx invalid: did not contain word/numbering.xml".to_string()))
+impl Docx {
+    /// the target of the numbering relationship,
+    /// or the name Word uses next to the main document if there is none
+    fn numbering_part_name(&self) -> Result<String> {
+        if let Some((_, name)) = self.related_parts(REL_NUMBERING)?.into_iter().next() {
+            return Ok(name);
         }
-
-        let xml_reader = Reader::from_str(xml_data.as_ref());
-
-        let (con_abs_map,abs_fmt_map) = read_numbering(xml_reader)?;
-        let con_fmt_map = join_numbering(con_abs_map, abs_fmt_map);
-        
-        Ok(con_fmt_map)
+        Ok(match part_dir(self.main_part_name()) {
+            "" => "numbering.xml".to_string(),
+            dir => format!("{}/numbering.xml", dir)
+        })
     }
 }
 
@@ -83,29 +127,43 @@ type abstractNumId = usize;
 #[allow(non_camel_case_types)]
 type numFmt = String;
 
-pub fn read_and_join_numbering<B: BufRead>(xml_reader: Reader<B>) -> io::Result<HashMap<numId,DocxNumbering>> {
+/// mapping from numId to abstractNumId (con-abs-part at the end of numbering.xml)
+type ConAbsMap = HashMap<numId,abstractNumId>;
+/// mapping from abstractNumId to the formats per level (abs-fmt-part at the beginning of numbering.xml)
+type AbsFmtMap = HashMap<abstractNumId,Vec<numFmt>>;
+
+pub fn read_and_join_numbering<B: BufRead>(xml_reader: Reader<B>) -> Result<HashMap<numId,DocxNumbering>> {
     let (con_abs_map,abs_fmt_map) = read_numbering(xml_reader)?;
-    Ok(join_numbering(con_abs_map, abs_fmt_map))
+    join_numbering(con_abs_map, abs_fmt_map)
 }
 
-fn join_numbering(con_abs_map: HashMap<numId,abstractNumId>, abs_fmt_map: HashMap<abstractNumId,Vec<numFmt>>) -> HashMap<numId,DocxNumbering> {
+fn join_numbering(con_abs_map: ConAbsMap, abs_fmt_map: AbsFmtMap) -> Result<HashMap<numId,DocxNumbering>> {
     let mut res = HashMap::new();
         // join maps (sql style)
     for (con_id,abs_id) in con_abs_map.iter() {
-        // select the first, ignore the rest
-        let fmt_str_levels = abs_fmt_map.get(abs_id).expect(&format!("abstractNumId {} was defined in con-abs-mapping but not in abs-fmt",abs_id));
-        let fmt_str = fmt_str_levels.get(0).expect(&format!("abstractNumId {} did not contain any formats ({})",abs_id,fmt_str_levels.len()));
-        let fmt = DocxNumFmt::read(fmt_str);
+        let fmt_str_levels = abs_fmt_map.get(abs_id).ok_or_else(|| Error::InvalidAttribute {
+            // defined in con-abs-mapping but not in abs-fmt
+            part: String::new(),
+            element: "w:abstractNumId".to_string(),
+            attribute: "w:val".to_string(),
+            value: abs_id.to_string()
+        })?;
+        // select the first, ignore the rest; an abstractNum may define no levels at all
+        let fmt = match fmt_str_levels.first() {
+            Some(fmt_str) => DocxNumFmt::read(fmt_str),
+            None => DocxNumFmt::None
+        };
         let r_entry = DocxNumbering { num_id: *con_id, format: fmt };
         res.insert(*con_id, r_entry);
     }
-    res
+    Ok(res)
 }
 
-fn read_numbering<B: BufRead>(mut xml_reader: Reader<B>) -> io::Result<(HashMap<numId,abstractNumId>,HashMap<abstractNumId,Vec<numFmt>>)> {
+fn read_numbering<B: BufRead>(xml_reader: Reader<B>) -> Result<(ConAbsMap,AbsFmtMap)> {
+    let mut xml_reader = NsReader::new(xml_reader);
     
-    let mut con_abs_map: HashMap<numId,abstractNumId> = HashMap::new(); // mapping from numId to abstractNumId at the end of the file
-    let mut abs_fmt_map: HashMap<abstractNumId,Vec<numFmt>> = HashMap::new(); // mapping from abstractNumId to fmt at the beginning of the file
+    let mut con_abs_map: ConAbsMap = HashMap::new(); // mapping from numId to abstractNumId at the end of the file
+    let mut abs_fmt_map: AbsFmtMap = HashMap::new(); // mapping from abstractNumId to fmt at the beginning of the file
 
     let mut buf = Vec::new();
     let mut abstract_num_id_opt = None; // abstract num id from abs-fmt-part (also appears in con-abs-part)
@@ -113,55 +171,43 @@ fn read_numbering<B: BufRead>(mut xml_reader: Reader<B>) -> io::Result<(HashMap<
     //let mut num_id = None;
     let mut fmt = Vec::new(); // format per indentation level (offset begins at "0") for current abstract_num_id
 
-    fn attr_as_string(event: &BytesStart, key: &[u8]) -> String {
-        let val_cstr = event.attributes().find(|a| a.as_ref().unwrap().key==key ).map(|a| a.unwrap().value ).expect(&format!("malformed word/comments.xml: missing attribute '{:?}' on tag 'comment'",key));
-        String::from_utf8(val_cstr.to_vec()).unwrap()
-    }
-    fn attr_as_usize(event: &BytesStart, key: &[u8]) -> usize {
-        let val_str = attr_as_string(event,key);
-        val_str.parse::<usize>().expect(&format!("comment attribute '{:?}' was not a number",key))
-    }
-
     loop {
         match xml_reader.read_event(&mut buf) {
 
-            Ok(Event::Start(ref e)) => {
-                match e.name() {
-                      b"w:abstractNum" => { // begin-end may appear toplvl (inside w:num only as Empty)
-                        abstract_num_id_opt = Some(attr_as_usize(e,b"w:abstractNumId"));
+            Ok((Ns::W, Event::Start(ref e))) => {
+                match e.local_name() {
+                      b"abstractNum" => { // begin-end may appear toplvl (inside w:num only as Empty)
+                        abstract_num_id_opt = Some(e.get_attr_as::<usize, _>(&xml_reader, Ns::W, b"abstractNumId")?);
                         //fmt = Vec::new(); // assume this has been reset before
                     }
-                    , b"w:lvl" => {} // ignore the w:ilvl attribute; assume they appear in order 0..n
-                    , b"w:num" => {
-                        num_id_opt = Some(attr_as_usize(e,b"w:numId"));
+                    , b"lvl" => {} // ignore the w:ilvl attribute; assume they appear in order 0..n
+                    , b"num" => {
+                        num_id_opt = Some(e.get_attr_as::<usize, _>(&xml_reader, Ns::W, b"numId")?);
                     }
                     , _ => ()
                 }
             }
-            , Ok(Event::Empty(ref e)) => {
-                match e.name() {
-                      b"w:abstractNumId" => { // found leaf of con-abs-entry
-                        let con_id = num_id_opt.unwrap();
-                        let abs_id = attr_as_usize(e,b"w:val");
+            , Ok((Ns::W, Event::Empty(ref e))) => {
+                match e.local_name() {
+                      b"abstractNumId" => { // found leaf of con-abs-entry
+                        let con_id = num_id_opt.ok_or_else(|| Error::xml(xml_reader.buffer_position(), "w:abstractNumId outside of w:num"))?;
+                        let abs_id = e.get_attr_as::<usize, _>(&xml_reader, Ns::W, b"val")?;
                         con_abs_map.insert(con_id, abs_id);
                         num_id_opt = None; // reset num id
                     }
-                    , b"w:numFmt" => {
-                        let lvl = attr_as_string(e,b"w:val");
+                    , b"numFmt" => {
+                        let lvl = e.get_attr(&xml_reader, Ns::W, b"val")?;
                         fmt.push(lvl);
                     }
                     , _ => ()
                 }
             }           
-            , Ok(Event::End(ref e)) => {
-                match e.name() {
-                    b"w:abstractNum" => { // found head of abs-fmt-entry
-                        let abstract_num_id = abstract_num_id_opt.unwrap();
+            , Ok((Ns::W, Event::End(ref e))) => {
+                if e.local_name() == b"abstractNum" { // found head of abs-fmt-entry
+                    if let Some(abstract_num_id) = abstract_num_id_opt.take() {
                         abs_fmt_map.insert(abstract_num_id, fmt);
-                        abstract_num_id_opt = None;
-                        fmt = Vec::new();
                     }
-                    , _ => ()
+                    fmt = Vec::new();
                 }
             }
             /*, Ok(Event::Text(e)) => {
@@ -170,11 +216,45 @@ fn read_numbering<B: BufRead>(mut xml_reader: Reader<B>) -> io::Result<(HashMap<
                     to_read = false;
                 }
             }*/
-            , Ok(Event::Eof) => break
+            , Ok((_, Event::Eof)) => break
             , Ok(_) => ()
-            , Err(e) => panic!("Error at position {}: {:?}", xml_reader.buffer_position(), e),
+            , Err(e) => return Err(Error::xml(xml_reader.buffer_position(), e)),
         }
     }
 
     Ok((con_abs_map, abs_fmt_map))
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+    use zip::write::{ZipWriter, FileOptions};
+
+    fn missing_part(docx: &Docx) -> String {
+        match docx.numbering() {
+            Err(Error::MissingPart(name)) => name,
+            _ => panic!("no error for a document without numbering")
+        }
+    }
+
+    #[test]
+    fn missing_numbering_is_named_by_its_relationship() {
+        let rels = |target: &str, rel_type: &str| format!(
+            r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/{}" Target="{}"/></Relationships>"#,
+            rel_type, target);
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for &(name, ref xml) in &[
+            ("_rels/.rels", rels("doc/main.xml", "officeDocument")),
+            ("doc/main.xml", r#"<w:document xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"/>"#.to_string()),
+            ("doc/_rels/main.xml.rels", rels("lists.xml", "numbering"))
+        ] {
+            zip.start_file(name, FileOptions::default()).unwrap();
+            zip.write_all(xml.as_bytes()).unwrap();
+        }
+        let docx = Docx::from_vec(zip.finish().unwrap().into_inner()).unwrap();
+        assert_eq!(missing_part(&docx), "doc/lists.xml");
+        // without relationship, the name Word uses
+        let docx = Docx::open("samples/sample-with-story-parts.docx").unwrap();
+        assert_eq!(missing_part(&docx), "word/numbering.xml");
+    }
+}
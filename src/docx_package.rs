@@ -0,0 +1,167 @@
+//! finding the parts of a package (Open Packaging Conventions, ECMA-376 part 2)
+//! the main document is the target of the officeDocument relationship in `_rels/.rels`,
+//! the other parts are targets of its own relationships;
+//! if a relationship part is missing, parts are found by their type in `[Content_Types].xml`
+
+use xml::reader::Reader;
+use xml::events::Event;
+
+use std::io::prelude::*;
+use std::rc::Rc;
+
+use ::Docx;
+use docx_rels::{Relationship, read_relationships, part_dir, rels_entry,
+    REL_OFFICE_DOCUMENT, REL_COMMENTS, REL_COMMENTS_EXTENDED, REL_NUMBERING,
+    REL_STYLES, REL_HEADER, REL_FOOTER, REL_FOOTNOTES, REL_ENDNOTES};
+use get_attr::GetAttr;
+use xml_ns::{Ns, NsReader};
+use error::{Error, Result};
+
+const CONTENT_TYPES: &str = "[Content_Types].xml";
+
+/// documents, templates and their macro-enabled variants
+const CT_MAIN: &[&str] = &[
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.document.main+xml",
+    "application/vnd.openxmlformats-officedocument.wordprocessingml.template.main+xml",
+    "application/vnd.ms-word.document.macroEnabled.main+xml",
+    "application/vnd.ms-word.template.macroEnabledTemplate.main+xml"
+];
+
+/// the content type of the parts that a relationship type refers to
+fn content_type_of(rel_type: &str) -> Option<&'static str> {
+    match rel_type {
+        REL_COMMENTS => Some("application/vnd.openxmlformats-officedocument.wordprocessingml.comments+xml")
+        , REL_COMMENTS_EXTENDED => Some("application/vnd.openxmlformats-officedocument.wordprocessingml.commentsExtended+xml")
+        , REL_NUMBERING => Some("application/vnd.openxmlformats-officedocument.wordprocessingml.numbering+xml")
+        , REL_STYLES => Some("application/vnd.openxmlformats-officedocument.wordprocessingml.styles+xml")
+        , REL_HEADER => Some("application/vnd.openxmlformats-officedocument.wordprocessingml.header+xml")
+        , REL_FOOTER => Some("application/vnd.openxmlformats-officedocument.wordprocessingml.footer+xml")
+        , REL_FOOTNOTES => Some("application/vnd.openxmlformats-officedocument.wordprocessingml.footnotes+xml")
+        , REL_ENDNOTES => Some("application/vnd.openxmlformats-officedocument.wordprocessingml.endnotes+xml")
+        , _ => None
+    }
+}
+
+/// the name of the main document part, e.g. "word/document.xml"
+/// falls back to the part name used by Word if the package describes no main part
+pub(crate) fn find_main_part(docx: &Docx) -> Result<String> {
+    if let Some(rels) = docx.relationships("")? {
+        if let Some(rel) = rels.iter().find(|r| !r.external && r.has_type(REL_OFFICE_DOCUMENT)) {
+            // spreadsheets and presentations have an officeDocument too
+            let name = rel.target_entry("");
+            match content_types(docx)?.into_iter().find(|(part, _)| part.trim_start_matches('/') == name) {
+                Some((_, ref content_type)) if !CT_MAIN.contains(&content_type.as_str()) =>
+                    return Err(Error::NotAWordDocument { part: name, content_type: content_type.clone() })
+                , _ => return Ok(name)
+            }
+        }
+    }
+    if let Some(name) = parts_of_type(docx, CT_MAIN)?.into_iter().next() {
+        return Ok(name);
+    }
+    Ok("word/document.xml".to_string())
+}
+
+/// the names of the parts with one of the content types, in order of `[Content_Types].xml`
+fn parts_of_type(docx: &Docx, types: &[&str]) -> Result<Vec<String>> {
+    Ok(content_types(docx)?.into_iter()
+        .filter(|(_, content_type)| types.contains(&content_type.as_str()))
+        .map(|(name, _)| name.trim_start_matches('/').to_string())
+        .collect())
+}
+
+/// the `<Override>` entries of `[Content_Types].xml`, none if the package lacks it
+fn content_types(docx: &Docx) -> Result<Vec<(String,String)>> {
+    match docx.part(CONTENT_TYPES)? {
+        Some(xml_data) => read_content_types(Reader::from_str(xml_data.as_ref()))
+            .map_err(|e| e.in_part(CONTENT_TYPES)),
+        None => Ok(vec![])
+    }
+}
+
+/// the part names and content types of the `<Override>` entries
+fn read_content_types<B: BufRead>(xml_reader: Reader<B>) -> Result<Vec<(String,String)>> {
+    let mut xml_reader = NsReader::new(xml_reader);
+    let mut buf = Vec::new();
+    let mut res = Vec::new();
+
+    loop {
+        match xml_reader.read_event(&mut buf) {
+            Ok((Ns::ContentTypes, Event::Empty(ref e))) | Ok((Ns::ContentTypes, Event::Start(ref e))) if e.local_name() == b"Override" => {
+                res.push((e.get_attr(&xml_reader, Ns::None, b"PartName")?, e.get_attr(&xml_reader, Ns::None, b"ContentType")?));
+            }
+            , Ok((_, Event::Eof)) => break
+            , Ok(_) => ()
+            , Err(e) => return Err(Error::xml(xml_reader.buffer_position(), e)),
+        }
+        buf.clear();
+    }
+
+    Ok(res)
+}
+
+impl Docx {
+    /// the relationships of a part, "" for those of the package
+    /// post: None if the part has no relationship part
+    pub(crate) fn relationships(&self, part_name: &str) -> Result<Option<Vec<Relationship>>> {
+        let name = rels_entry(part_name);
+        match self.part(&name)? {
+            Some(xml_data) => Ok(Some(read_relationships(Reader::from_str(xml_data.as_ref()))
+                .map_err(|e| e.in_part(&name))?)),
+            None => Ok(None)
+        }
+    }
+
+    /// the parts that the main document refers to with a relationship type,
+    /// as relationship and zip entry name, in order of the relationships
+    /// parts found via `[Content_Types].xml` have a relationship without id
+    pub(crate) fn related_parts(&self, rel_type: &str) -> Result<Vec<(Relationship,String)>> {
+        let main = self.main_part_name();
+        match self.relationships(main)? {
+            Some(rels) => Ok(rels.into_iter()
+                .filter(|r| !r.external && r.has_type(rel_type))
+                .map(|r| {
+                    let name = r.target_entry(part_dir(main));
+                    (r, name)
+                })
+                .collect()),
+            None => {
+                let content_types: Vec<&str> = content_type_of(rel_type).into_iter().collect();
+                Ok(parts_of_type(self, &content_types)?.into_iter()
+                    .map(|name| {
+                        let rel = Relationship { id: String::new(), rel_type: rel_type.to_string(), target: format!("/{}", name), external: false };
+                        (rel, name)
+                    })
+                    .collect())
+            }
+        }
+    }
+
+    /// the name and xml of the first part that the main document refers to with a relationship type
+    /// post: None if there is no such part, or the package lacks it
+    pub(crate) fn related_part(&self, rel_type: &str) -> Result<Option<(String,Rc<String>)>> {
+        for (_, name) in self.related_parts(rel_type)? {
+            if let Some(xml_data) = self.part(&name)? {
+                return Ok(Some((name, xml_data)));
+            }
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use doc::MsDoc;
+
+    #[test]
+    fn rejects_other_office_documents() {
+        match Docx::open("samples/sample.xlsx") {
+            Err(Error::NotAWordDocument { part, .. }) => assert_eq!(part, "xl/workbook.xml"),
+            Err(e) => panic!("unexpected error: {}", e),
+            Ok(_) => panic!("a spreadsheet opened as a Word document")
+        }
+        assert!(Docx::open("samples/sample.pptx").is_err());
+        assert_eq!(Docx::open("samples/sample.docx").unwrap().main_part_name(), "word/document.xml");
+    }
+}
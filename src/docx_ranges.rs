@@ -0,0 +1,71 @@
+//! ranges of the text of a document that carry an annotation,
+//! e.g. the range a comment refers to, or highlighted text
+
+use std::fmt;
+
+use ::Docx;
+use docx::{TextPosition, StoryPart};
+use docx_comments::RangeRecovery;
+use docx_highlights::Shading;
+use docx_walk::Extract;
+use error::Result;
+
+/// what a range is annotated with
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum RangeKind {
+    /// the range a comment refers to
+    Comment,
+    /// text marked with a highlight color
+    Highlight
+}
+
+impl fmt::Display for RangeKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            RangeKind::Comment => write!(f, "comment"),
+            RangeKind::Highlight => write!(f, "highlight")
+        }
+    }
+}
+
+/// the id of a comment, or of what else a range is annotated with
+pub trait RangeId {
+    fn id(&self) -> usize;
+}
+
+/// a range of text with an annotation
+pub trait AnnotatedRange: RangeId {
+    fn kind(&self) -> RangeKind;
+    /// the annotation as shown to users, e.g. the comment id or the highlight color
+    fn label(&self) -> String;
+    /// the text inside the range
+    fn text(&self) -> &str;
+    /// the part of the document the range lies in
+    fn part(&self) -> &StoryPart;
+    /// where the range begins in the plain text of its part
+    fn start(&self) -> TextPosition;
+    /// where the range ends (exclusive) in the plain text of its part
+    fn end(&self) -> TextPosition;
+}
+
+impl Docx {
+    /// the commented ranges and highlights, in one pass over the document
+    /// ranges of the main document come first, ordered by their start,
+    /// then the commented ranges of the other parts
+    pub fn annotated_ranges(&self) -> Result<Vec<Box<dyn AnnotatedRange>>> {
+        let extracted = self.extract(Extract { commented: Some(RangeRecovery::Drop), highlighted: Some(Shading::Ignore), ..Extract::default() })?;
+        let mut res: Vec<Box<dyn AnnotatedRange>> = Vec::new();
+        if let Some((commented, _)) = extracted.commented {
+            res.extend(commented.into_iter().map(|c| Box::new(c) as Box<dyn AnnotatedRange>));
+        }
+        if let Some(highlighted) = extracted.highlighted {
+            res.extend(highlighted.into_iter().map(|h| Box::new(h) as Box<dyn AnnotatedRange>));
+        }
+        // stable, keeps the order of the other parts
+        res.sort_by_key(|r| match *r.part() {
+            StoryPart::Main => (0, r.start(), r.end()),
+            _ => (1, TextPosition::default(), TextPosition::default())
+        });
+        Ok(res)
+    }
+}
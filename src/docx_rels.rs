@@ -0,0 +1,148 @@
+//! relationships of a package part, as stored in `_rels/<part>.rels`
+
+use xml::reader::Reader;
+use xml::events::Event;
+
+use std::io::prelude::*;
+
+use get_attr::GetAttr;
+use xml_ns::{Ns, NsReader};
+use error::{Error, Result};
+
+/// relationship types are given as in transitional documents,
+/// strict documents use the same names under `REL_STRICT_PREFIX`
+const REL_PREFIX: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships/";
+const REL_STRICT_PREFIX: &str = "http://purl.oclc.org/ooxml/officeDocument/relationships/";
+
+pub const REL_OFFICE_DOCUMENT: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument";
+pub const REL_COMMENTS: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships/comments";
+pub const REL_COMMENTS_EXTENDED: &str = "http://schemas.microsoft.com/office/2011/relationships/commentsExtended";
+pub const REL_NUMBERING: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships/numbering";
+pub const REL_STYLES: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles";
+pub const REL_HEADER: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships/header";
+pub const REL_FOOTER: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships/footer";
+pub const REL_FOOTNOTES: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships/footnotes";
+pub const REL_ENDNOTES: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships/endnotes";
+
+pub struct Relationship {
+    pub id: String,
+    pub rel_type: String,
+    pub target: String,
+    /// true for targets outside the package, e.g. hyperlinks
+    pub external: bool
+}
+
+impl Relationship {
+    /// true if the relationship is of the given type, or of its strict equivalent
+    pub fn has_type(&self, rel_type: &str) -> bool {
+        if self.rel_type == rel_type {
+            return true;
+        }
+        match (rel_type.starts_with(REL_PREFIX), self.rel_type.starts_with(REL_STRICT_PREFIX)) {
+            (true, true) => rel_type[REL_PREFIX.len()..] == self.rel_type[REL_STRICT_PREFIX.len()..],
+            _ => false
+        }
+    }
+
+    /// the zip entry name of an internal target, relative to the directory of the source part
+    /// e.g. "header1.xml" from "word/document.xml" is "word/header1.xml",
+    /// and "../customXml/item1.xml" is "customXml/item1.xml"
+    pub fn target_entry(&self, source_dir: &str) -> String {
+        let mut segments: Vec<&str> = if self.target.starts_with('/') {
+            vec![]
+        } else {
+            source_dir.split('/').filter(|s| !s.is_empty()).collect()
+        };
+        for segment in self.target.split('/') {
+            match segment {
+                "" | "." => ()
+                , ".." => { segments.pop(); }
+                , _ => segments.push(segment)
+            }
+        }
+        segments.join("/")
+    }
+}
+
+/// the directory of a part, e.g. "word" for "word/document.xml"
+pub fn part_dir(part_name: &str) -> &str {
+    match part_name.rfind('/') {
+        Some(i) => &part_name[..i],
+        None => ""
+    }
+}
+
+/// the zip entry that holds the relationships of a part,
+/// e.g. "word/_rels/document.xml.rels"; the package itself ("") has "_rels/.rels"
+pub fn rels_entry(part_name: &str) -> String {
+    let dir = part_dir(part_name);
+    let file = match part_name.rfind('/') {
+        Some(i) => &part_name[i + 1..],
+        None => part_name
+    };
+    if dir.is_empty() {
+        format!("_rels/{}.rels", file)
+    } else {
+        format!("{}/_rels/{}.rels", dir, file)
+    }
+}
+
+pub(crate) fn read_relationships<B: BufRead>(xml_reader: Reader<B>) -> Result<Vec<Relationship>> {
+    let mut xml_reader = NsReader::new(xml_reader);
+    let mut buf = Vec::new();
+    let mut res = Vec::new();
+
+    loop {
+        match xml_reader.read_event(&mut buf) {
+            Ok((Ns::Relationships, Event::Empty(ref e))) | Ok((Ns::Relationships, Event::Start(ref e))) if e.local_name() == b"Relationship" => {
+                let relationship = Relationship {
+                    id: e.get_attr(&xml_reader, Ns::None, b"Id")?,
+                    rel_type: e.get_attr(&xml_reader, Ns::None, b"Type")?,
+                    target: e.get_attr(&xml_reader, Ns::None, b"Target")?,
+                    external: e.get_attr_opt(&xml_reader, Ns::None, b"TargetMode")?.map(|m| m == "External").unwrap_or(false)
+                };
+                res.push(relationship);
+            }
+            , Ok((_, Event::Eof)) => break
+            , Ok(_) => ()
+            , Err(e) => return Err(Error::xml(xml_reader.buffer_position(), e)),
+        }
+        buf.clear();
+    }
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STRICT_RELS: &str = r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+        <Relationship Id="rId1" Type="http://purl.oclc.org/ooxml/officeDocument/relationships/officeDocument" Target="word/document.xml"/>
+        <Relationship Id="rId2" Type="http://purl.oclc.org/ooxml/officeDocument/relationships/hyperlink" Target="https://example.com/" TargetMode="External"/>
+    </Relationships>"#;
+
+    fn relationship(rel_type: &str) -> Relationship {
+        Relationship { id: "rId1".to_string(), rel_type: rel_type.to_string(), target: "comments.xml".to_string(), external: false }
+    }
+
+    #[test]
+    fn strict_types_match_transitional_ones() {
+        let strict = relationship("http://purl.oclc.org/ooxml/officeDocument/relationships/comments");
+        assert!(strict.has_type(REL_COMMENTS));
+        assert!(!strict.has_type(REL_NUMBERING));
+        // types outside the officeDocument relationships have no strict form
+        assert!(!strict.has_type(REL_COMMENTS_EXTENDED));
+        assert!(relationship(REL_COMMENTS).has_type(REL_COMMENTS));
+        assert!(!relationship(REL_COMMENTS).has_type("http://purl.oclc.org/ooxml/officeDocument/relationships/comments"));
+    }
+
+    #[test]
+    fn reads_strict_relationships() {
+        let rels = read_relationships(Reader::from_str(STRICT_RELS)).unwrap();
+        assert_eq!(rels.len(), 2);
+        assert!(rels[0].has_type(REL_OFFICE_DOCUMENT) && !rels[0].external);
+        assert_eq!(rels[0].target_entry(""), "word/document.xml");
+        assert!(rels[1].external);
+    }
+}
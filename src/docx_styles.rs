@@ -0,0 +1,226 @@
+//! the styles of a document (`word/styles.xml`) and the formatting that runs inherit from them:
+//! the document defaults, then the paragraph style, then the character style of the run,
+//! each with the styles it is based on, and last the direct formatting of the run
+
+use xml::reader::Reader;
+use xml::events::Event;
+
+use std::collections::{HashMap, HashSet};
+use std::io::prelude::*;
+
+use ::Docx;
+use docx_events::{ParagraphProperties, RunProperties, shading_fill};
+use docx_rels::REL_STYLES;
+use get_attr::GetAttr;
+use xml_ns::{Ns, NsReader};
+use error::{Error, Result};
+
+/// a `w:style`, with the run properties it sets
+#[derive(Clone, Debug, Default)]
+struct Style {
+    based_on: Option<String>,
+    run: RunProperties
+}
+
+/// the paragraph and character styles of a document,
+/// with the run properties that `RunProperties` covers
+#[derive(Clone, Debug, Default)]
+pub struct Styles {
+    defaults: RunProperties, // w:docDefaults
+    paragraph_styles: HashMap<String, Style>,
+    character_styles: HashMap<String, Style>,
+    default_paragraph_style: Option<String>,
+    default_character_style: Option<String>
+}
+
+impl Styles {
+    /// the effective formatting of a run in a paragraph:
+    /// document defaults, paragraph style, character style and direct formatting,
+    /// each overriding what the ones before set;
+    /// a paragraph or run without (known) style has the default style of its type
+    /// post: the style is that of the run
+    pub fn run_properties(&self, paragraph: Option<&ParagraphProperties>, run: &RunProperties) -> RunProperties {
+        let paragraph_style = paragraph.and_then(|p| p.style.as_ref())
+            .filter(|id| self.paragraph_styles.contains_key(*id))
+            .or(self.default_paragraph_style.as_ref());
+        let character_style = run.style.as_ref()
+            .filter(|id| self.character_styles.contains_key(*id))
+            .or(self.default_character_style.as_ref());
+
+        let mut res = self.defaults.clone();
+        for style in based_on_chain(&self.paragraph_styles, paragraph_style).iter().rev() {
+            inherit(&mut res, &style.run);
+        }
+        for style in based_on_chain(&self.character_styles, character_style).iter().rev() {
+            inherit(&mut res, &style.run);
+        }
+        inherit(&mut res, run);
+        res.style = run.style.clone();
+        res
+    }
+}
+
+/// a style and the styles it is based on, from the style itself to the first one;
+/// the chain ends at an unknown id, and before a style that is already in it
+fn based_on_chain<'a>(styles: &'a HashMap<String, Style>, id: Option<&'a String>) -> Vec<&'a Style> {
+    let mut res = Vec::new();
+    let mut seen = HashSet::new();
+    let mut next = id;
+    while let Some(id) = next {
+        match styles.get(id) {
+            Some(style) if seen.insert(id) => {
+                res.push(style);
+                next = style.based_on.as_ref();
+            }
+            , _ => break
+        }
+    }
+    res
+}
+
+/// take over what `props` sets
+fn inherit(res: &mut RunProperties, props: &RunProperties) {
+    if props.highlight.is_some() {
+        res.highlight = props.highlight.clone();
+    }
+    if props.shading.is_some() {
+        res.shading = props.shading.clone();
+    }
+}
+
+impl Docx {
+    /// the styles of the document, none if it has no styles part
+    pub fn styles(&self) -> Result<Styles> {
+        match self.related_part(REL_STYLES)? {
+            Some((name, xml_data)) => read_styles(Reader::from_str(xml_data.as_ref())).map_err(|e| e.in_part(&name)),
+            None => Ok(Styles::default())
+        }
+    }
+}
+
+pub fn read_styles<B: BufRead>(xml_reader: Reader<B>) -> Result<Styles> {
+    let mut xml_reader = NsReader::new(xml_reader);
+    let mut res = Styles::default();
+
+    let mut buf = Vec::new();
+    let mut depth = 0;
+    let mut style: Option<(usize, String, String, Style)> = None; // depth, type, id and properties of the current w:style
+    let mut defaults_depth = None; // depth of w:rPrDefault
+    let mut props_depth = None; // depth of the w:rPr that is read, its children are the properties
+
+    loop {
+        buf.clear();
+        match xml_reader.read_event(&mut buf) {
+            Ok((Ns::W, Event::Start(ref e))) => {
+                depth += 1;
+                match e.local_name() {
+                    b"style" => {
+                        let style_type = e.get_attr_opt(&xml_reader, Ns::W, b"type")?.unwrap_or_else(|| "paragraph".to_string());
+                        let id = e.get_attr_opt(&xml_reader, Ns::W, b"styleId")?.unwrap_or_default();
+                        if e.get_attr_on_off(&xml_reader, Ns::W, b"default")? == Some(true) {
+                            match style_type.as_str() {
+                                "paragraph" => res.default_paragraph_style = Some(id.clone())
+                                , "character" => res.default_character_style = Some(id.clone())
+                                , _ => ()
+                            }
+                        }
+                        style = Some((depth, style_type, id, Style::default()));
+                    }
+                    , b"rPrDefault" => defaults_depth = Some(depth)
+                    // not the w:rPr of a paragraph mark, in w:pPr
+                    , b"rPr" if style.as_ref().map(|s| s.0 + 1) == Some(depth) || defaults_depth.map(|d| d + 1) == Some(depth) => {
+                        props_depth = Some(depth);
+                    }
+                    , _ => ()
+                }
+            }
+            , Ok((Ns::W, Event::Empty(ref e))) => {
+                let props = if props_depth != Some(depth) {
+                    None
+                } else if let Some((_, _, _, ref mut style)) = style {
+                    Some(&mut style.run)
+                } else {
+                    Some(&mut res.defaults)
+                };
+                match (e.local_name(), props) {
+                    (b"basedOn", _) => {
+                        if let Some((style_depth, _, _, ref mut style)) = style {
+                            if style_depth == depth {
+                                style.based_on = Some(e.get_attr(&xml_reader, Ns::W, b"val")?);
+                            }
+                        }
+                    }
+                    , (b"highlight", Some(props)) => props.highlight = Some(e.get_attr(&xml_reader, Ns::W, b"val")?)
+                    , (b"shd", Some(props)) => props.shading = shading_fill(&xml_reader, e)?
+                    , _ => ()
+                }
+            }
+            , Ok((Ns::W, Event::End(ref e))) => {
+                if props_depth == Some(depth) {
+                    props_depth = None;
+                }
+                match e.local_name() {
+                    b"style" => {
+                        if let Some((_, style_type, id, s)) = style.take() {
+                            match style_type.as_str() {
+                                "paragraph" => { res.paragraph_styles.insert(id, s); }
+                                , "character" => { res.character_styles.insert(id, s); }
+                                , _ => () // table and numbering styles
+                            }
+                        }
+                    }
+                    , b"rPrDefault" => defaults_depth = None
+                    , _ => ()
+                }
+                depth = depth.saturating_sub(1);
+            }
+            , Ok((_, Event::Start(_))) => depth += 1
+            , Ok((_, Event::End(_))) => depth = depth.saturating_sub(1)
+            , Ok((_, Event::Eof)) => break
+            , Ok(_) => ()
+            , Err(e) => return Err(Error::xml(xml_reader.buffer_position(), e)),
+        }
+    }
+
+    Ok(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STYLES: &str = r#"<w:styles xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main">
+        <w:docDefaults><w:rPrDefault><w:rPr><w:shd w:val="clear" w:fill="FFFF00"/></w:rPr></w:rPrDefault></w:docDefaults>
+        <w:style w:type="paragraph" w:default="1" w:styleId="Normal"/>
+        <w:style w:type="paragraph" w:styleId="Base"><w:pPr><w:rPr><w:highlight w:val="blue"/></w:rPr></w:pPr><w:rPr><w:highlight w:val="yellow"/></w:rPr></w:style>
+        <w:style w:type="paragraph" w:styleId="Quote"><w:basedOn w:val="Base"/></w:style>
+        <w:style w:type="character" w:styleId="Strong"><w:rPr><w:highlight w:val="red"/></w:rPr></w:style>
+        <w:style w:type="character" w:styleId="A"><w:basedOn w:val="B"/></w:style>
+        <w:style w:type="character" w:styleId="B"><w:basedOn w:val="A"/></w:style>
+    </w:styles>"#;
+
+    fn highlight(paragraph_style: Option<&str>, run_style: Option<&str>, direct: Option<&str>) -> Option<String> {
+        let styles = read_styles(Reader::from_str(STYLES)).unwrap();
+        let paragraph = ParagraphProperties { style: paragraph_style.map(String::from), ..ParagraphProperties::default() };
+        let run = RunProperties { style: run_style.map(String::from), highlight: direct.map(String::from), ..RunProperties::default() };
+        styles.run_properties(Some(&paragraph), &run).highlight
+    }
+
+    #[test]
+    fn later_layers_override_earlier_ones() {
+        assert_eq!(highlight(None, None, None), None);
+        // not the highlight of the paragraph mark
+        assert_eq!(highlight(Some("Quote"), None, None), Some("yellow".to_string()));
+        assert_eq!(highlight(Some("Quote"), Some("Strong"), None), Some("red".to_string()));
+        assert_eq!(highlight(Some("Quote"), Some("Strong"), Some("none")), Some("none".to_string()));
+        assert_eq!(highlight(Some("Unknown"), Some("A"), None), None);
+    }
+
+    #[test]
+    fn defaults_apply_to_every_run() {
+        let styles = read_styles(Reader::from_str(STYLES)).unwrap();
+        let run = RunProperties { shading: Some("auto".to_string()), ..RunProperties::default() };
+        assert_eq!(styles.run_properties(None, &RunProperties::default()).shading, Some("FFFF00".to_string()));
+        assert_eq!(styles.run_properties(None, &run).shading, Some("auto".to_string()));
+    }
+}
@@ -0,0 +1,259 @@
+//! one pass over a story part that feeds several visitors,
+//! e.g. the commented ranges, highlights and plain text of word/document.xml
+//! visitors are the extension point for extractions the crate does not offer
+
+use xml::reader::Reader;
+
+use std::io::prelude::*;
+
+use ::Docx;
+use docx::{TextPosition, StoryPart, ParagraphExtractor, walk_story_parts};
+use docx_events::{DocxEvent, DocxEvents, ParagraphProperties, RunProperties, Element};
+use docx_comments::{DocxCommented, CommentDiagnostic, RangeRecovery, CommentedExtractor};
+use docx_highlights::{DocxHighlight, HighlightExtractor, Shading};
+use docx_numberings::{NumberedParagraph, NumberingExtractor};
+use docx_styles::Styles;
+use xml_ns::Ns;
+use error::Result;
+
+/// where a walk is in a story part, the context of every callback of a `Visitor`
+/// positions are those of the plain text of the part:
+/// the text of every `w:t`, and a newline at the end of each paragraph
+#[derive(Clone, Debug, Default)]
+pub struct Walk {
+    paragraph_count: usize, // paragraphs begun so far
+    offset: usize, // characters of plain text read so far
+    paragraphs: Vec<ParagraphProperties>, // the paragraphs the walk is in, more than one in text boxes
+    runs: Vec<RunProperties>, // likewise for runs
+    open_comments: Vec<usize>
+}
+
+impl Walk {
+    /// the paragraph the walk is in, or the last one between paragraphs
+    pub fn paragraph(&self) -> usize {
+        self.paragraph_count.max(1) - 1
+    }
+
+    pub fn in_paragraph(&self) -> bool {
+        !self.paragraphs.is_empty()
+    }
+
+    /// where a range that begins here starts,
+    /// between paragraphs a range starts with the next paragraph
+    pub fn start(&self) -> TextPosition {
+        let paragraph = if self.in_paragraph() { self.paragraph_count - 1 } else { self.paragraph_count };
+        TextPosition { paragraph, offset: self.offset }
+    }
+
+    /// where a range that ends here ends,
+    /// between paragraphs a range ends with the previous paragraph
+    pub fn end(&self) -> TextPosition {
+        TextPosition { paragraph: self.paragraph(), offset: self.offset }
+    }
+
+    /// the properties of the current paragraph
+    pub fn paragraph_properties(&self) -> Option<&ParagraphProperties> {
+        self.paragraphs.last()
+    }
+
+    /// the style id of the current paragraph, e.g. "Heading1"
+    pub fn paragraph_style(&self) -> Option<&str> {
+        self.paragraph_properties().and_then(|p| p.style.as_ref()).map(|s| s.as_str())
+    }
+
+    /// the properties of the current run
+    pub fn run_properties(&self) -> Option<&RunProperties> {
+        self.runs.last()
+    }
+
+    /// the highlight color of the current run, e.g. "yellow",
+    /// as set in its direct formatting; `Styles::run_properties` adds what the run inherits
+    pub fn highlight(&self) -> Option<&str> {
+        self.run_properties().and_then(|r| r.highlight.as_ref()).map(|h| h.as_str())
+    }
+
+    /// the fill color of the shading of the current run, e.g. "FFFF00", likewise
+    pub fn shading(&self) -> Option<&str> {
+        self.run_properties().and_then(|r| r.shading.as_ref()).map(|s| s.as_str())
+    }
+
+    /// the ids of the comment ranges that began and did not yet end, in order of their start
+    pub fn open_comments(&self) -> &[usize] {
+        &self.open_comments
+    }
+}
+
+/// a marker of a comment range, see `Visitor::range_marker`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RangeMarker {
+    /// `w:commentRangeStart`
+    CommentStart(usize),
+    /// `w:commentRangeEnd`
+    CommentEnd(usize),
+    /// `w:commentReference`, the comment mark
+    CommentReference(usize)
+}
+
+/// callbacks for the content of a story part, in document order
+/// `walk` is the context: a start is seen inside the paragraph or run,
+/// an end, text or marker before it takes effect,
+/// e.g. the offset of a text is that of its first character
+/// and a range marker is not yet among the open comments
+pub trait Visitor {
+    fn paragraph_start(&mut self, _walk: &Walk) {}
+    fn paragraph_end(&mut self, _walk: &Walk) {}
+    fn run_start(&mut self, _walk: &Walk) {}
+    fn run_end(&mut self, _walk: &Walk) {}
+    /// text of the current run
+    fn text(&mut self, _text: &str, _walk: &Walk) {}
+    fn range_marker(&mut self, _marker: RangeMarker, _walk: &Walk) {}
+    /// an element of `elements`, e.g. `w:bookmarkStart`
+    fn element_start(&mut self, _element: &Element, _walk: &Walk) {}
+    /// the end of an element of `elements`, by namespace and local name
+    fn element_end(&mut self, _ns: Ns, _name: &str, _walk: &Walk) {}
+
+    /// the elements to report to `element_start` and `element_end`,
+    /// by namespace and local name, e.g. `(Ns::W, "bookmarkStart")`
+    fn elements(&self) -> Vec<(Ns, &'static str)> {
+        vec![]
+    }
+}
+
+impl Walk {
+    /// pass an event to every visitor, and update the walk
+    fn visit(&mut self, event: DocxEvent, visitors: &mut [&mut dyn Visitor]) {
+        match event {
+            DocxEvent::ParagraphStart(props) => {
+                self.paragraph_count += 1;
+                self.paragraphs.push(props);
+                for v in visitors.iter_mut() { v.paragraph_start(self); }
+            }
+            , DocxEvent::ParagraphEnd => {
+                for v in visitors.iter_mut() { v.paragraph_end(self); }
+                self.paragraphs.pop();
+                self.offset += 1; // paragraph break
+            }
+            , DocxEvent::RunStart(props) => {
+                self.runs.push(props);
+                for v in visitors.iter_mut() { v.run_start(self); }
+            }
+            , DocxEvent::RunEnd => {
+                for v in visitors.iter_mut() { v.run_end(self); }
+                self.runs.pop();
+            }
+            , DocxEvent::Text(ref text) => {
+                for v in visitors.iter_mut() { v.text(text, self); }
+                self.offset += text.chars().count();
+            }
+            , DocxEvent::CommentRangeStart(a_id) => {
+                for v in visitors.iter_mut() { v.range_marker(RangeMarker::CommentStart(a_id), self); }
+                self.open_comments.retain(|&id| id != a_id);
+                self.open_comments.push(a_id);
+            }
+            , DocxEvent::CommentRangeEnd(a_id) => {
+                for v in visitors.iter_mut() { v.range_marker(RangeMarker::CommentEnd(a_id), self); }
+                self.open_comments.retain(|&id| id != a_id);
+            }
+            , DocxEvent::CommentReference(a_id) => {
+                for v in visitors.iter_mut() { v.range_marker(RangeMarker::CommentReference(a_id), self); }
+            }
+            , DocxEvent::ElementStart(ref element) => {
+                for v in visitors.iter_mut() { v.element_start(element, self); }
+            }
+            , DocxEvent::ElementEnd(ns, ref name) => {
+                for v in visitors.iter_mut() { v.element_end(ns, name, self); }
+            }
+        }
+    }
+}
+
+/// feed the events of a story part to every visitor, in order;
+/// e.g. `walk(docx.events()?, &mut [&mut my_visitor])` streams the main document
+/// post: the walk at the end of the part
+pub fn walk<B: BufRead>(mut events: DocxEvents<B>, visitors: &mut [&mut dyn Visitor]) -> Result<Walk> {
+    for v in visitors.iter() {
+        for (ns, name) in v.elements() {
+            events.report(ns, name);
+        }
+    }
+    let mut walk = Walk::default();
+    for event in events {
+        walk.visit(event?, visitors);
+    }
+    Ok(walk)
+}
+
+/// walk over the xml of a part, `name` is the part name for errors
+pub(crate) fn walk_part(xml_data: &str, name: &str, visitors: &mut [&mut dyn Visitor]) -> Result<Walk> {
+    walk(DocxEvents::new(Reader::from_str(xml_data), name), visitors)
+}
+
+/// what `Docx::extract` reads in its pass over the document
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Extract {
+    /// the text of each paragraph
+    pub text: bool,
+    /// the ranges that comments refer to, in all story parts,
+    /// with what to do with ranges that are never closed
+    pub commented: Option<RangeRecovery>,
+    /// the highlighted ranges, with whether run shading counts as highlighting
+    pub highlighted: Option<Shading>,
+    /// the paragraphs that are list items
+    pub numbering: bool
+}
+
+/// the results of `Docx::extract`, None for what was not asked for
+/// all but the commented ranges are those of the main document
+#[derive(Default)]
+pub struct Extracted {
+    /// the text of each paragraph, without the newline
+    pub text: Option<Vec<String>>,
+    pub commented: Option<(Vec<DocxCommented>,Vec<CommentDiagnostic>)>,
+    pub highlighted: Option<Vec<DocxHighlight>>,
+    pub numbering: Option<Vec<NumberedParagraph>>
+}
+
+impl Docx {
+    /// walk over the main document with several visitors at once
+    pub fn visit(&self, visitors: &mut [&mut dyn Visitor]) -> Result<Walk> {
+        let xml_data = self.required_part(self.main_part_name())?;
+        walk_part(xml_data.as_ref(), self.main_part_name(), visitors)
+    }
+
+    /// read several things at once, with one parse of each story part
+    pub fn extract(&self, what: Extract) -> Result<Extracted> {
+        let mut res = Extracted::default();
+        let styles = if what.highlighted.is_some() { self.styles()? } else { Styles::default() };
+        walk_story_parts(self, |part, walker| {
+            let main = *part == StoryPart::Main;
+            let mut commented = what.commented.map(|recovery| CommentedExtractor::new(part, recovery));
+            let mut text = if main && what.text { Some(ParagraphExtractor::default()) } else { None };
+            let mut highlighted = if main { what.highlighted.map(|shading| HighlightExtractor::new(shading, styles.clone())) } else { None };
+            let mut numbering = if main && what.numbering { Some(NumberingExtractor::default()) } else { None };
+
+            let walk = {
+                let mut extractors: Vec<&mut dyn Visitor> = Vec::new();
+                if let Some(ref mut e) = commented { extractors.push(e); }
+                if let Some(ref mut e) = text { extractors.push(e); }
+                if let Some(ref mut e) = highlighted { extractors.push(e); }
+                if let Some(ref mut e) = numbering { extractors.push(e); }
+                if extractors.is_empty() {
+                    return Ok(());
+                }
+                walker(&mut extractors)?
+            };
+
+            if let Some(e) = commented {
+                let (ranges, diagnostics) = e.finish(&walk);
+                let all = res.commented.get_or_insert_with(Default::default);
+                all.0.extend(ranges);
+                all.1.extend(diagnostics);
+            }
+            if let Some(e) = text { res.text = Some(e.finish()); }
+            if let Some(e) = highlighted { res.highlighted = Some(e.finish()); }
+            if let Some(e) = numbering { res.numbering = Some(e.finish()); }
+            Ok(())
+        })?;
+        Ok(res)
+    }
+}
@@ -0,0 +1,111 @@
+//! errors of reading a document
+
+use zip::result::ZipError;
+
+use std::error;
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum Error {
+    /// the file could not be read
+    Io(io::Error),
+    /// the file is not a zip archive, or uses features of zip that are not supported
+    NotAZip(String),
+    /// a part that is required is missing from the package, e.g. "word/document.xml"
+    MissingPart(String),
+    /// the main part of the package is not a Word document, e.g. the workbook of a spreadsheet
+    NotAWordDocument { part: String, content_type: String },
+    /// a part is not well-formed xml, `position` is the byte offset in the part (0 if unknown)
+    MalformedXml { part: String, position: usize, message: String },
+    /// an element lacks a required attribute
+    MissingAttribute { part: String, element: String, attribute: String },
+    /// an attribute has a value that cannot be read, e.g. an id that is not a number
+    InvalidAttribute { part: String, element: String, attribute: String, value: String }
+}
+
+pub type Result<T> = ::std::result::Result<T, Error>;
+
+impl Error {
+    /// an xml error at a byte offset of the part that is being read
+    /// the name of the part is added by `in_part`
+    pub(crate) fn xml<E: fmt::Display>(position: usize, err: E) -> Error {
+        Error::MalformedXml { part: String::new(), position, message: err.to_string() }
+    }
+
+    /// an error of reading the zip entry of a part, a missing entry is a missing part
+    pub(crate) fn zip_entry(name: &str, err: ZipError) -> Error {
+        match err {
+            ZipError::FileNotFound => Error::MissingPart(name.to_string()),
+            e => e.into()
+        }
+    }
+
+    /// set the name of the part, unless it is already known
+    pub(crate) fn in_part(mut self, name: &str) -> Error {
+        match self {
+            Error::MalformedXml { ref mut part, .. }
+            | Error::MissingAttribute { ref mut part, .. }
+            | Error::InvalidAttribute { ref mut part, .. } if part.is_empty() => {
+                *part = name.to_string();
+            }
+            _ => ()
+        }
+        self
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref e) => write!(f, "cannot read file: {}", e),
+            Error::NotAZip(ref msg) => write!(f, "not a zip archive: {}", msg),
+            Error::MissingPart(ref name) => write!(f, "invalid document: did not contain {}", name),
+            Error::NotAWordDocument { ref part, ref content_type } =>
+                write!(f, "not a Word document: {} has content type {}", part, content_type),
+            Error::MalformedXml { ref part, position, ref message } =>
+                write!(f, "malformed {} at position {}: {}", part, position, message),
+            Error::MissingAttribute { ref part, ref element, ref attribute } =>
+                write!(f, "malformed {}: missing attribute '{}' on '{}'", part, attribute, element),
+            Error::InvalidAttribute { ref part, ref element, ref attribute, ref value } =>
+                write!(f, "malformed {}: invalid value \"{}\" of attribute '{}' on '{}'", part, value, attribute, element)
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io(e)
+    }
+}
+
+impl From<ZipError> for Error {
+    fn from(e: ZipError) -> Error {
+        match e {
+            ZipError::Io(e) => Error::Io(e),
+            ZipError::InvalidArchive(msg) | ZipError::UnsupportedArchive(msg) => Error::NotAZip(msg.to_string()),
+            // entries read by name go through `Error::zip_entry`, which knows the name
+            ZipError::FileNotFound => Error::MissingPart("an entry of the archive".to_string())
+        }
+    }
+}
+
+/// for callers that work with `io::Result`
+impl From<Error> for io::Error {
+    fn from(e: Error) -> io::Error {
+        match e {
+            Error::Io(e) => e,
+            Error::MissingPart(_) => io::Error::new(io::ErrorKind::NotFound, e.to_string()),
+            _ => io::Error::new(io::ErrorKind::InvalidData, e.to_string())
+        }
+    }
+}
@@ -1,13 +1,56 @@
 use xml::events::BytesStart;
+use xml::errors::Error as XmlError;
 
-/// get an xml attribute by key and return is string value
+use std::str::FromStr;
+
+use error::{Error, Result};
+use xml_ns::{Ns, ResolveNs};
+
+/// get an xml attribute by namespace and local name and return is string value
+/// `names` resolves the prefixes in scope, usually the reader the element came from
+/// errors do not know the part they occur in, readers add it with `Error::in_part`
 pub trait GetAttr {
-    fn get_attr(&self, key:&[u8]) -> String;
+    fn get_attr<R: ResolveNs>(&self, names: &R, ns: Ns, key:&[u8]) -> Result<String>;
+    /// like `get_attr`, but for optional attributes
+    fn get_attr_opt<R: ResolveNs>(&self, names: &R, ns: Ns, key:&[u8]) -> Result<Option<String>>;
+    /// like `get_attr`, but parse the value, e.g. a number
+    fn get_attr_as<T: FromStr, R: ResolveNs>(&self, names: &R, ns: Ns, key:&[u8]) -> Result<T>;
+    /// read an optional attribute of type ST_OnOff ("true", "1", "on", ...)
+    fn get_attr_on_off<R: ResolveNs>(&self, names: &R, ns: Ns, key:&[u8]) -> Result<Option<bool>>;
 }
 
 impl<'a> GetAttr for BytesStart<'a> {
-    fn get_attr(&self, key:&[u8]) -> String {
-        let cstr = self.attributes().find(|a| a.as_ref().unwrap().key==key ).map(|a| a.unwrap().value ).expect(&format!("malformed word/comments.xml: missing attribute '{:?}' on tag 'comment'",key));
-        String::from_utf8(cstr.to_vec()).unwrap()
+    fn get_attr<R: ResolveNs>(&self, names: &R, ns: Ns, key:&[u8]) -> Result<String> {
+        self.get_attr_opt(names, ns, key)?.ok_or_else(|| Error::MissingAttribute {
+            part: String::new(),
+            element: String::from_utf8_lossy(self.name()).into_owned(),
+            attribute: format!("{}{}", ns.prefix(), String::from_utf8_lossy(key))
+        })
     }
-}
\ No newline at end of file
+
+    fn get_attr_opt<R: ResolveNs>(&self, names: &R, ns: Ns, key:&[u8]) -> Result<Option<String>> {
+        let malformed = |e: XmlError| Error::xml(names.buffer_position(), format!("attributes of '{}': {}", String::from_utf8_lossy(self.name()), e));
+        for attr in self.attributes() {
+            let attr = attr.map_err(&malformed)?;
+            if names.resolve_attr(attr.key) == (ns, key) {
+                let cstr = attr.unescaped_value().map_err(&malformed)?; // e.g. "&amp;" in hyperlink targets
+                return Ok(Some(String::from_utf8_lossy(&cstr).into_owned()));
+            }
+        }
+        Ok(None)
+    }
+
+    fn get_attr_as<T: FromStr, R: ResolveNs>(&self, names: &R, ns: Ns, key:&[u8]) -> Result<T> {
+        let value = self.get_attr(names, ns, key)?;
+        value.parse::<T>().map_err(|_| Error::InvalidAttribute {
+            part: String::new(),
+            element: String::from_utf8_lossy(self.name()).into_owned(),
+            attribute: format!("{}{}", ns.prefix(), String::from_utf8_lossy(key)),
+            value: value.clone()
+        })
+    }
+
+    fn get_attr_on_off<R: ResolveNs>(&self, names: &R, ns: Ns, key:&[u8]) -> Result<Option<bool>> {
+        Ok(self.get_attr_opt(names, ns, key)?.map(|v| v == "1" || v == "true" || v == "on"))
+    }
+}
@@ -1,6 +1,6 @@
 #![allow(unused_imports, dead_code, unused_must_use)]
 
-/**
+/*
  * Copyright 2017 Robin Syihab. All rights reserved.
  *
  * Permission is hereby granted, free of charge, to any person obtaining a copy of this software
@@ -24,15 +24,27 @@
 
 extern crate zip;
 extern crate quick_xml as xml;
+extern crate chrono;
 
 
+pub mod error;
 pub mod doc;
 pub mod docx;
 
+pub use error::Error;
 pub use doc::MsDoc;
-pub use docx::Docx;
+pub use docx::{Docx, TextPosition, StoryPart, HeaderFooterKind};
 
 pub mod docx_comments;
+pub mod docx_comment_body;
+pub mod docx_rels;
+pub mod docx_package;
 pub mod docx_numberings;
 pub mod docx_highlights;
-pub mod get_attr;
\ No newline at end of file
+pub mod docx_styles;
+pub mod docx_events;
+pub mod docx_walk;
+pub mod docx_ranges;
+pub mod get_attr;
+pub mod xml_ns;
+
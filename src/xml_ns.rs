@@ -0,0 +1,171 @@
+//! namespaces of the xml parts
+//! elements and attributes are recognised by namespace URI, not by prefix,
+//! as producers are free to bind a namespace to any prefix (or the default namespace)
+
+use xml::reader::Reader;
+use xml::events::{Event, BytesText};
+use xml::errors::Result as XmlResult;
+
+use std::io::prelude::*;
+
+use error::{Error, Result};
+
+pub const NS_W: &[u8] = b"http://schemas.openxmlformats.org/wordprocessingml/2006/main";
+pub const NS_R: &[u8] = b"http://schemas.openxmlformats.org/officeDocument/2006/relationships";
+/// the namespaces of ISO 29500 Strict documents
+pub const NS_W_STRICT: &[u8] = b"http://purl.oclc.org/ooxml/wordprocessingml/main";
+pub const NS_R_STRICT: &[u8] = b"http://purl.oclc.org/ooxml/officeDocument/relationships";
+pub const NS_W14: &[u8] = b"http://schemas.microsoft.com/office/word/2010/wordml";
+pub const NS_W15: &[u8] = b"http://schemas.microsoft.com/office/word/2012/wordml";
+pub const NS_RELATIONSHIPS: &[u8] = b"http://schemas.openxmlformats.org/package/2006/relationships";
+pub const NS_CONTENT_TYPES: &[u8] = b"http://schemas.openxmlformats.org/package/2006/content-types";
+
+/// the namespaces known to the readers
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Ns {
+    /// no namespace, e.g. unprefixed attributes
+    None,
+    /// WordprocessingML, transitional or strict
+    W,
+    /// relationship references, e.g. `r:id`, transitional or strict
+    R,
+    /// extensions of Word 2010, e.g. `w14:paraId`
+    W14,
+    /// extensions of Word 2013, e.g. `w15:commentEx`
+    W15,
+    /// the relationship parts of the package
+    Relationships,
+    /// `[Content_Types].xml`
+    ContentTypes,
+    Other
+}
+
+impl Ns {
+    pub fn from_uri(uri: Option<&[u8]>) -> Ns {
+        match uri {
+            None => Ns::None
+            , Some(NS_W) | Some(NS_W_STRICT) => Ns::W
+            , Some(NS_R) | Some(NS_R_STRICT) => Ns::R
+            , Some(NS_W14) => Ns::W14
+            , Some(NS_W15) => Ns::W15
+            , Some(NS_RELATIONSHIPS) => Ns::Relationships
+            , Some(NS_CONTENT_TYPES) => Ns::ContentTypes
+            , Some(_) => Ns::Other
+        }
+    }
+
+    /// the prefix Word uses for the namespace, for messages
+    pub fn prefix(&self) -> &'static str {
+        match *self {
+            Ns::W => "w:"
+            , Ns::R => "r:"
+            , Ns::W14 => "w14:"
+            , Ns::W15 => "w15:"
+            , _ => ""
+        }
+    }
+}
+
+/// resolves the namespace of attributes in the scope of the current element
+pub trait ResolveNs {
+    /// the namespace and local name of a (possibly prefixed) attribute name
+    fn resolve_attr<'a>(&self, key: &'a [u8]) -> (Ns, &'a [u8]);
+    /// the position in the xml after the current element, for errors
+    fn buffer_position(&self) -> usize;
+}
+
+/// a reader that resolves the namespace of every element
+pub(crate) struct NsReader<B: BufRead> {
+    reader: Reader<B>,
+    ns_buf: Vec<u8>
+}
+
+impl<B: BufRead> NsReader<B> {
+    pub fn new(reader: Reader<B>) -> NsReader<B> {
+        NsReader { reader, ns_buf: Vec::new() }
+    }
+
+    /// the next event, with the namespace of its element
+    /// events other than Start, Empty and End have no namespace
+    pub fn read_event<'b>(&mut self, buf: &'b mut Vec<u8>) -> XmlResult<(Ns, Event<'b>)> {
+        let (ns, event) = self.reader.read_namespaced_event(buf, &mut self.ns_buf)?;
+        Ok((Ns::from_uri(ns), event))
+    }
+
+    pub fn buffer_position(&self) -> usize {
+        self.reader.buffer_position()
+    }
+
+    /// the unescaped text of a Text event
+    pub fn text(&self, e: &BytesText) -> Result<String> {
+        e.unescape_and_decode(&self.reader).map_err(|e| Error::xml(self.reader.buffer_position(), e))
+    }
+}
+
+impl<B: BufRead> ResolveNs for NsReader<B> {
+    fn resolve_attr<'a>(&self, key: &'a [u8]) -> (Ns, &'a [u8]) {
+        let (ns, local) = self.reader.resolve_namespace(key, &self.ns_buf);
+        (Ns::from_uri(ns), local)
+    }
+
+    fn buffer_position(&self) -> usize {
+        self.reader.buffer_position()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use get_attr::GetAttr;
+
+    #[test]
+    fn strict_namespaces_are_those_of_transitional_documents() {
+        assert_eq!(Ns::from_uri(Some(NS_W_STRICT)), Ns::W);
+        assert_eq!(Ns::from_uri(Some(NS_R_STRICT)), Ns::R);
+        assert_eq!(Ns::from_uri(Some(b"http://purl.oclc.org/ooxml/drawingml/main")), Ns::Other);
+        assert_eq!(Ns::from_uri(None), Ns::None);
+    }
+
+    #[test]
+    fn resolves_strict_elements_and_attributes() {
+        let xml = r#"<x:sectPr xmlns:x="http://purl.oclc.org/ooxml/wordprocessingml/main"
+            xmlns:y="http://purl.oclc.org/ooxml/officeDocument/relationships"><x:headerReference x:type="first" y:id="rId7"/></x:sectPr>"#;
+        let mut reader = NsReader::new(Reader::from_str(xml));
+        let mut buf = Vec::new();
+        loop {
+            match reader.read_event(&mut buf).unwrap() {
+                (Ns::W, Event::Empty(ref e)) => {
+                    assert_eq!(e.local_name(), b"headerReference");
+                    assert_eq!(e.get_attr(&reader, Ns::W, b"type").unwrap(), "first");
+                    assert_eq!(e.get_attr(&reader, Ns::R, b"id").unwrap(), "rId7");
+                    return;
+                }
+                (_, Event::Eof) => panic!("no w:headerReference"),
+                _ => ()
+            }
+            buf.clear();
+        }
+    }
+
+    #[test]
+    fn malformed_attributes_report_their_position() {
+        let xml = r#"<w:p xmlns:w="http://schemas.openxmlformats.org/wordprocessingml/2006/main"><w:pStyle w:val="a&bogus;"/></w:p>"#;
+        let mut reader = NsReader::new(Reader::from_str(xml));
+        let mut buf = Vec::new();
+        loop {
+            match reader.read_event(&mut buf).unwrap() {
+                (Ns::W, Event::Empty(ref e)) => {
+                    match e.get_attr(&reader, Ns::W, b"val") {
+                        Err(Error::MalformedXml { position, .. }) => assert_eq!(position, reader.buffer_position()),
+                        _ => panic!("no error for an unknown entity")
+                    }
+                    assert!(reader.buffer_position() > 0);
+                    return;
+                }
+                (_, Event::Eof) => panic!("no w:pStyle"),
+                _ => ()
+            }
+            buf.clear();
+        }
+    }
+}